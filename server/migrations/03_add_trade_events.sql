@@ -0,0 +1,19 @@
+-- Ledger of ingested Trade events, keyed by their on-chain identity.
+-- Balance updates in `trades` are only applied when a row is newly inserted here,
+-- so re-querying a block range (restart, retry, inclusive range boundary) is harmless.
+CREATE TABLE IF NOT EXISTS trade_events (
+    id BIGSERIAL PRIMARY KEY,
+    chain_type VARCHAR(20) NOT NULL,
+    tx_hash VARCHAR(100) NOT NULL,  -- Monad transaction hash / Sui txDigest
+    log_index BIGINT NOT NULL,      -- Monad log index / Sui eventSeq
+    block_number BIGINT,            -- Monad block number, NULL for Sui
+    trader VARCHAR(66) NOT NULL,
+    subject VARCHAR(66) NOT NULL,
+    is_buy BOOLEAN NOT NULL,
+    share_amount NUMERIC NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(chain_type, tx_hash, log_index)
+);
+
+CREATE INDEX IF NOT EXISTS idx_trade_events_chain_block ON trade_events(chain_type, block_number);
+CREATE INDEX IF NOT EXISTS idx_trade_events_trader_subject ON trade_events(trader, subject);
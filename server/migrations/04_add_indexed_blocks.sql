@@ -0,0 +1,10 @@
+-- Hashes of recently indexed EVM blocks, used to detect chain reorganizations.
+-- Only a window of blocks behind the sync cursor is kept.
+CREATE TABLE IF NOT EXISTS indexed_blocks (
+    chain_type VARCHAR(20) NOT NULL,
+    block_number BIGINT NOT NULL,
+    block_hash VARCHAR(66) NOT NULL,
+    parent_hash VARCHAR(66) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (chain_type, block_number)
+);
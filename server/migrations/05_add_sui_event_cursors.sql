@@ -0,0 +1,26 @@
+-- Position of the Sui event query pagination, replaces the JSON stored in sync_status.metadata
+CREATE TABLE IF NOT EXISTS sui_event_cursors (
+    chain_type VARCHAR(20) NOT NULL PRIMARY KEY,
+    tx_digest VARCHAR(64) NOT NULL,  -- txDigest of the last processed event (base58)
+    event_seq BIGINT NOT NULL,       -- eventSeq of the last processed event
+    checkpoint BIGINT,               -- checkpoint containing that transaction, if known
+    timestamp_ms BIGINT,             -- timestamp of that event
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TRIGGER update_sui_event_cursors_modtime
+    BEFORE UPDATE ON sui_event_cursors
+    FOR EACH ROW
+    EXECUTE PROCEDURE update_modified_column();
+
+-- Carry over cursors previously stored in sync_status.metadata
+INSERT INTO sui_event_cursors (chain_type, tx_digest, event_seq)
+SELECT DISTINCT ON (chain_type)
+    chain_type,
+    metadata::json->>'txDigest',
+    (metadata::json->>'eventSeq')::BIGINT
+FROM sync_status
+WHERE chain_type = 'sui' AND metadata LIKE '{%'
+ORDER BY chain_type, id DESC
+ON CONFLICT (chain_type) DO NOTHING;
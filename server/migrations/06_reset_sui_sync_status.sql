@@ -0,0 +1,4 @@
+-- The Sui event cursor now lives in sui_event_cursors (see 05), and sync_status rows for
+-- Sui only ever held a meaningless block number. Checkpoint mode stores the last processed
+-- checkpoint in sync_status, so drop the stale rows to let it start from SUI_START_CHECKPOINT.
+DELETE FROM sync_status WHERE chain_type = 'sui';
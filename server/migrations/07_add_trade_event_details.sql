@@ -0,0 +1,14 @@
+-- Keep every Trade event verbatim in the ledger, so analytics, audits and projection
+-- rebuilds don't need to query the RPC again. NULL for events ingested before.
+ALTER TABLE trade_events
+    ADD COLUMN IF NOT EXISTS price NUMERIC,               -- Monad ethAmount / Sui price
+    ADD COLUMN IF NOT EXISTS protocol_fee NUMERIC,        -- Monad protocolEthAmount / Sui protocol_fee
+    ADD COLUMN IF NOT EXISTS subject_fee NUMERIC,         -- Monad subjectEthAmount / Sui subject_fee
+    ADD COLUMN IF NOT EXISTS supply NUMERIC,              -- Subject supply emitted with the event
+    ADD COLUMN IF NOT EXISTS block_hash VARCHAR(66),      -- Monad block hash, NULL for Sui
+    ADD COLUMN IF NOT EXISTS checkpoint BIGINT,           -- Sui checkpoint, NULL for Monad
+    ADD COLUMN IF NOT EXISTS event_timestamp_ms BIGINT,   -- Block / checkpoint timestamp
+    ADD COLUMN IF NOT EXISTS raw_event JSONB;             -- Log / event as returned by the node
+
+CREATE INDEX IF NOT EXISTS idx_trade_events_chain_checkpoint ON trade_events(chain_type, checkpoint);
+CREATE INDEX IF NOT EXISTS idx_trade_events_subject_time ON trade_events(chain_type, subject, event_timestamp_ms);
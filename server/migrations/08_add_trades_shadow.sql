@@ -0,0 +1,9 @@
+-- Shadow copy of `trades` rebuilt by the reindex command from the trade_events ledger.
+-- Rows of a chain are verified here before being swapped into `trades`.
+CREATE TABLE IF NOT EXISTS trades_shadow (
+    trader VARCHAR(66) NOT NULL,
+    subject VARCHAR(66) NOT NULL,
+    share_amount NUMERIC NOT NULL,
+    chain_type VARCHAR(20) NOT NULL,
+    PRIMARY KEY (trader, subject, chain_type)
+);
@@ -0,0 +1,15 @@
+-- Holdings whose indexed balance in `trades` did not match the on-chain balance
+-- when checked by the reconciler
+CREATE TABLE IF NOT EXISTS share_discrepancies (
+    id BIGSERIAL PRIMARY KEY,
+    chain_type VARCHAR(20) NOT NULL,
+    trader VARCHAR(66) NOT NULL,
+    subject VARCHAR(66) NOT NULL,
+    indexed_amount NUMERIC NOT NULL,
+    onchain_amount NUMERIC NOT NULL,
+    corrected BOOLEAN NOT NULL DEFAULT FALSE,  -- trades was overwritten with the on-chain balance
+    detected_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_share_discrepancies_holding ON share_discrepancies(chain_type, trader, subject);
+CREATE INDEX IF NOT EXISTS idx_share_discrepancies_detected_at ON share_discrepancies(detected_at);
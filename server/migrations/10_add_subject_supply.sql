@@ -0,0 +1,41 @@
+-- Supply of each subject as emitted by its latest ingested Trade event
+CREATE TABLE IF NOT EXISTS subject_supply (
+    chain_type VARCHAR(20) NOT NULL,
+    subject VARCHAR(66) NOT NULL,
+    supply NUMERIC NOT NULL,
+    last_tx_hash VARCHAR(100) NOT NULL,
+    last_log_index BIGINT NOT NULL,
+    last_block_number BIGINT,  -- Monad block of the latest event, NULL for Sui
+    last_checkpoint BIGINT,    -- Sui checkpoint of the latest event, NULL for Monad
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (chain_type, subject)
+);
+
+CREATE TRIGGER update_subject_supply_modtime
+    BEFORE UPDATE ON subject_supply
+    FOR EACH ROW
+    EXECUTE PROCEDURE update_modified_column();
+
+-- Events whose supply did not continue the previous supply of their subject,
+-- meaning an event in between was missed or duplicated
+CREATE TABLE IF NOT EXISTS supply_gaps (
+    id BIGSERIAL PRIMARY KEY,
+    chain_type VARCHAR(20) NOT NULL,
+    subject VARCHAR(66) NOT NULL,
+    expected_supply NUMERIC NOT NULL,
+    event_supply NUMERIC NOT NULL,
+    tx_hash VARCHAR(100) NOT NULL,
+    log_index BIGINT NOT NULL,
+    rescan_from BIGINT,  -- Block (Monad) or checkpoint (Sui) range to re-fetch, NULL if unknown
+    rescan_to BIGINT,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',  -- pending, rescanned, skipped, failed
+    detected_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_supply_gaps_status ON supply_gaps(status);
+
+CREATE TRIGGER update_supply_gaps_modtime
+    BEFORE UPDATE ON supply_gaps
+    FOR EACH ROW
+    EXECUTE PROCEDURE update_modified_column();
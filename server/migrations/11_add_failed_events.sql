@@ -0,0 +1,36 @@
+-- Dead-letter queue of Trade events that failed to apply. Each row keeps the full
+-- event so it can be retried with backoff, or replayed through the admin API.
+CREATE TABLE IF NOT EXISTS failed_events (
+    id BIGSERIAL PRIMARY KEY,
+    chain_type VARCHAR(20) NOT NULL,
+    tx_hash VARCHAR(100) NOT NULL,
+    log_index BIGINT NOT NULL,
+    block_number BIGINT,
+    trader VARCHAR(66) NOT NULL,
+    subject VARCHAR(66) NOT NULL,
+    is_buy BOOLEAN NOT NULL,
+    share_amount NUMERIC NOT NULL,
+    price NUMERIC NOT NULL,
+    protocol_fee NUMERIC NOT NULL,
+    subject_fee NUMERIC NOT NULL,
+    supply NUMERIC NOT NULL,
+    block_hash VARCHAR(66),
+    checkpoint BIGINT,
+    event_timestamp_ms BIGINT,
+    raw_event JSONB NOT NULL,
+    error TEXT NOT NULL,                             -- Error of the latest attempt
+    attempts INTEGER NOT NULL DEFAULT 1,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',   -- pending, dead (out of attempts), resolved
+    next_retry_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(chain_type, tx_hash, log_index)
+);
+
+CREATE INDEX IF NOT EXISTS idx_failed_events_due ON failed_events(status, next_retry_at);
+CREATE INDEX IF NOT EXISTS idx_failed_events_chain_block ON failed_events(chain_type, block_number);
+
+CREATE TRIGGER update_failed_events_modtime
+    BEFORE UPDATE ON failed_events
+    FOR EACH ROW
+    EXECUTE PROCEDURE update_modified_column();
@@ -0,0 +1,25 @@
+-- Telegram permission changes written in the same transaction as the balance change
+-- that caused them, and executed by the outbox worker
+CREATE TABLE IF NOT EXISTS telegram_outbox (
+    id BIGSERIAL PRIMARY KEY,
+    dedup_key VARCHAR(200) NOT NULL UNIQUE,        -- Identity of the cause, e.g. chain:tx_hash:log_index
+    action VARCHAR(10) NOT NULL,                   -- grant, revoke
+    bot_token VARCHAR NOT NULL,
+    chat_group_id VARCHAR NOT NULL,
+    telegram_id VARCHAR(50) NOT NULL,
+    chain_type VARCHAR(20) NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',  -- pending, sent, superseded, dead (out of attempts)
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_telegram_outbox_due ON telegram_outbox(status, next_attempt_at);
+CREATE INDEX IF NOT EXISTS idx_telegram_outbox_member ON telegram_outbox(chat_group_id, telegram_id, status);
+
+CREATE TRIGGER update_telegram_outbox_modtime
+    BEFORE UPDATE ON telegram_outbox
+    FOR EACH ROW
+    EXECUTE PROCEDURE update_modified_column();
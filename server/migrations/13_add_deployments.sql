@@ -0,0 +1,28 @@
+-- Several SharesTrading deployments per chain: an EVM contract address or a Sui SharesTrading
+-- object id. Rows written before deployments were tracked have an empty deployment and are
+-- assigned to the first configured deployment of their chain on startup.
+ALTER TABLE trade_events ADD COLUMN IF NOT EXISTS deployment VARCHAR(100) NOT NULL DEFAULT '';
+ALTER TABLE failed_events ADD COLUMN IF NOT EXISTS deployment VARCHAR(100) NOT NULL DEFAULT '';
+ALTER TABLE supply_gaps ADD COLUMN IF NOT EXISTS deployment VARCHAR(100) NOT NULL DEFAULT '';
+ALTER TABLE share_discrepancies ADD COLUMN IF NOT EXISTS deployment VARCHAR(100) NOT NULL DEFAULT '';
+ALTER TABLE telegram_bots ADD COLUMN IF NOT EXISTS deployment VARCHAR(100) NOT NULL DEFAULT '';
+
+-- Holdings and supplies are tracked per market
+ALTER TABLE trades ADD COLUMN IF NOT EXISTS deployment VARCHAR(100) NOT NULL DEFAULT '';
+ALTER TABLE trades DROP CONSTRAINT IF EXISTS trades_trader_subject_chain_type_key;
+ALTER TABLE trades ADD CONSTRAINT trades_trader_subject_chain_type_deployment_key UNIQUE (trader, subject, chain_type, deployment);
+
+ALTER TABLE trades_shadow ADD COLUMN IF NOT EXISTS deployment VARCHAR(100) NOT NULL DEFAULT '';
+ALTER TABLE trades_shadow DROP CONSTRAINT IF EXISTS trades_shadow_pkey;
+ALTER TABLE trades_shadow ADD PRIMARY KEY (trader, subject, chain_type, deployment);
+
+ALTER TABLE subject_supply ADD COLUMN IF NOT EXISTS deployment VARCHAR(100) NOT NULL DEFAULT '';
+ALTER TABLE subject_supply DROP CONSTRAINT IF EXISTS subject_supply_pkey;
+ALTER TABLE subject_supply ADD PRIMARY KEY (chain_type, deployment, subject);
+
+CREATE INDEX IF NOT EXISTS idx_telegram_bots_subject_deployment ON telegram_bots(subject_address, chain_type, deployment);
+
+-- One event query cursor per Trade event type, package upgrades change the type
+ALTER TABLE sui_event_cursors ADD COLUMN IF NOT EXISTS event_type VARCHAR(200) NOT NULL DEFAULT '';
+ALTER TABLE sui_event_cursors DROP CONSTRAINT IF EXISTS sui_event_cursors_pkey;
+ALTER TABLE sui_event_cursors ADD PRIMARY KEY (chain_type, event_type);
@@ -0,0 +1,13 @@
+-- Single-use nonces issued by POST /challenge, the signed message proves address ownership
+CREATE TABLE IF NOT EXISTS auth_challenges (
+    nonce VARCHAR(64) PRIMARY KEY,
+    telegram_id VARCHAR(50) NOT NULL,
+    chat_id VARCHAR NOT NULL,
+    chain_type VARCHAR(20) NOT NULL,
+    message TEXT NOT NULL,                          -- Exact text the wallet signs
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE,               -- Set once a signature of the nonce is accepted
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_auth_challenges_expires_at ON auth_challenges(expires_at);
@@ -0,0 +1,18 @@
+-- Outbox rows reference their bot instead of copying its token, the worker reads the
+-- current token from telegram_bots so rotated tokens apply to pending changes
+ALTER TABLE telegram_outbox ADD COLUMN IF NOT EXISTS agent_name VARCHAR;
+
+UPDATE telegram_outbox
+SET agent_name = telegram_bots.agent_name
+FROM telegram_bots
+WHERE telegram_bots.bot_token = telegram_outbox.bot_token
+    AND telegram_bots.chat_group_id = telegram_outbox.chat_group_id;
+
+-- Changes of bots that no longer exist cannot be executed
+UPDATE telegram_outbox
+SET status = 'dead', last_error = 'Bot no longer exists'
+WHERE agent_name IS NULL AND status = 'pending';
+UPDATE telegram_outbox SET agent_name = '' WHERE agent_name IS NULL;
+
+ALTER TABLE telegram_outbox ALTER COLUMN agent_name SET NOT NULL;
+ALTER TABLE telegram_outbox DROP COLUMN IF EXISTS bot_token;
@@ -0,0 +1,117 @@
+/// Block range a log query starts with
+pub const INITIAL_BLOCK_RANGE: u64 = 100;
+
+/// Queries returning fewer logs than this grow the range
+const SPARSE_LOG_COUNT: usize = 100;
+/// Queries returning more logs than this shrink the range
+const DENSE_LOG_COUNT: usize = 1000;
+
+/// Error fragments EVM nodes use to reject a log query for its size
+const RANGE_TOO_LARGE_ERRORS: &[&str] = &[
+    "block range too large",
+    "exceed maximum block range",
+    "block range is too wide",
+    "eth_getlogs is limited to",
+    "too many results",
+    "too many logs",
+    "query returned more than",
+    "log response size exceeded",
+    "exceeds max results",
+    "query timeout exceeded",
+];
+
+/// Sizes `eth_getLogs` block ranges: grows while results are sparse,
+/// halves down to `min` when the node rejects a range as too large.
+/// A range of size `n` queries `from..=from + n`, so `to - from` never exceeds the size.
+#[derive(Debug)]
+pub struct BlockRangeSizer {
+    size: u64,
+    min: u64,
+    max: u64,
+}
+
+impl BlockRangeSizer {
+    pub fn new(initial: u64, min: u64, max: u64) -> Self {
+        let min = min.max(1);
+        let max = max.max(min);
+        Self {
+            size: initial.clamp(min, max),
+            min,
+            max,
+        }
+    }
+
+    /// Number of blocks after the range start to query
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Adjust the range after a successful query returning `log_count` logs
+    pub fn record_success(&mut self, log_count: usize) {
+        if log_count < SPARSE_LOG_COUNT {
+            self.size = self.size.saturating_mul(2).min(self.max);
+        } else if log_count > DENSE_LOG_COUNT {
+            self.size = (self.size / 2).max(self.min);
+        }
+    }
+
+    /// Halve the range after the node rejected it, returns false if it cannot shrink further
+    pub fn shrink(&mut self) -> bool {
+        if self.size <= self.min {
+            return false;
+        }
+        self.size = (self.size / 2).max(self.min);
+        true
+    }
+}
+
+/// Whether a log query error means the range or its result set was too large
+pub fn is_range_too_large(error: &anyhow::Error) -> bool {
+    let message = format!("{:#}", error).to_lowercase();
+    RANGE_TOO_LARGE_ERRORS.iter().any(|fragment| message.contains(fragment))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_range_grows_when_sparse_and_halves_when_dense() {
+        let mut range = BlockRangeSizer::new(100, 10, 1000);
+
+        range.record_success(0);
+        assert_eq!(range.size(), 200);
+        range.record_success(500);
+        assert_eq!(range.size(), 200);
+        range.record_success(5000);
+        assert_eq!(range.size(), 100);
+
+        for _ in 0..10 {
+            range.record_success(0);
+        }
+        assert_eq!(range.size(), 1000);
+    }
+
+    #[test]
+    fn test_range_shrinks_down_to_min() {
+        let mut range = BlockRangeSizer::new(100, 30, 1000);
+
+        assert!(range.shrink());
+        assert_eq!(range.size(), 50);
+        assert!(range.shrink());
+        assert_eq!(range.size(), 30);
+        assert!(!range.shrink());
+        assert_eq!(range.size(), 30);
+    }
+
+    #[test]
+    fn test_is_range_too_large() {
+        assert!(is_range_too_large(&anyhow!("(code: -32005, message: query returned more than 10000 results)")));
+        assert!(is_range_too_large(&anyhow!("eth_getLogs block range too large, max is 1000")));
+        assert!(is_range_too_large(&anyhow!("eth_getLogs is limited to a 10,000 range")));
+        assert!(is_range_too_large(&anyhow!("Log response size exceeded. You can make eth_getLogs requests with up to a 2K block range")));
+        assert!(!is_range_too_large(&anyhow!("connection reset by peer")));
+        assert!(!is_range_too_large(&anyhow!("eth_call is limited to 50000000 gas")));
+    }
+}
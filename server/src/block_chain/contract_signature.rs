@@ -0,0 +1,251 @@
+use anyhow::{Result, anyhow};
+use ethers::abi::{self, ParamType, Token};
+use ethers::prelude::*;
+use ethers::types::transaction::eip2718::TypedTransaction;
+use serde::Deserialize;
+use serde_json::json;
+
+/// Selector of `isValidSignature(bytes32,bytes)`, also returned by it for a valid signature (EIP-1271)
+const EIP1271_MAGIC_VALUE: [u8; 4] = [0x16, 0x26, 0xba, 0x7e];
+/// Suffix of signatures from wallets that are not deployed yet (ERC-6492)
+const ERC6492_MAGIC_SUFFIX: [u8; 32] = [
+    0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92,
+    0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92,
+];
+
+/// Signature of a counterfactual wallet, with the factory call that deploys it
+#[derive(Debug, PartialEq)]
+pub struct Erc6492Signature {
+    pub factory: Address,
+    pub factory_calldata: Bytes,
+    pub signature: Bytes,
+}
+
+/// Block returned by `eth_simulateV1`
+#[derive(Debug, Deserialize)]
+struct SimulatedBlock {
+    calls: Vec<SimulatedCall>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SimulatedCall {
+    #[serde(rename = "returnData")]
+    return_data: Bytes,
+    status: U64,
+}
+
+/// Unwrap an ERC-6492 signature, `None` when it is not wrapped
+pub fn parse_erc6492(signature: &[u8]) -> Result<Option<Erc6492Signature>> {
+    let Some(wrapped) = signature.strip_suffix(&ERC6492_MAGIC_SUFFIX) else {
+        return Ok(None);
+    };
+    let tokens = abi::decode(&[ParamType::Address, ParamType::Bytes, ParamType::Bytes], wrapped)
+        .map_err(|e| anyhow!("Malformed ERC-6492 signature: {}", e))?;
+    match tokens.as_slice() {
+        [Token::Address(factory), Token::Bytes(factory_calldata), Token::Bytes(signature)] => Ok(Some(Erc6492Signature {
+            factory: *factory,
+            factory_calldata: factory_calldata.clone().into(),
+            signature: signature.clone().into(),
+        })),
+        _ => Err(anyhow!("Malformed ERC-6492 signature")),
+    }
+}
+
+/// Calldata of `isValidSignature(hash, signature)`
+fn is_valid_signature_calldata(hash: H256, signature: &[u8]) -> Bytes {
+    let arguments = abi::encode(&[Token::FixedBytes(hash.as_bytes().to_vec()), Token::Bytes(signature.to_vec())]);
+    [EIP1271_MAGIC_VALUE.as_slice(), &arguments].concat().into()
+}
+
+/// Whether `isValidSignature` returned the magic value, a left-aligned bytes4
+fn is_magic_value(return_data: &[u8]) -> bool {
+    return_data.len() == 32 && return_data[..4] == EIP1271_MAGIC_VALUE
+}
+
+/// Check a smart contract wallet's signature of `hash` with its `isValidSignature`.
+/// ERC-6492 signatures of wallets that are not deployed yet are checked by simulating the
+/// deployment followed by the check with `eth_simulateV1`. Addresses without code are not valid.
+pub async fn verify_contract_signature<M: Middleware>(provider: &M, signer: Address, hash: H256, signature: &[u8]) -> Result<bool> {
+    let deployed = !provider.get_code(signer, None).await
+        .map_err(|e| anyhow!("Failed to get code of {:#x}: {}", signer, e))?
+        .is_empty();
+
+    match parse_erc6492(signature)? {
+        // Deployed since signing, the wallet checks the inner signature itself
+        Some(wrapped) if deployed => call_is_valid_signature(provider, signer, hash, &wrapped.signature).await,
+        Some(wrapped) => simulate_counterfactual(provider, signer, hash, &wrapped).await,
+        None if deployed => call_is_valid_signature(provider, signer, hash, signature).await,
+        None => Ok(false),
+    }
+}
+
+async fn call_is_valid_signature<M: Middleware>(provider: &M, signer: Address, hash: H256, signature: &[u8]) -> Result<bool> {
+    let tx: TypedTransaction = TransactionRequest::new()
+        .to(signer)
+        .data(is_valid_signature_calldata(hash, signature))
+        .into();
+    let return_data = provider.call(&tx, None).await
+        .map_err(|e| anyhow!("isValidSignature of {:#x} failed: {}", signer, e))?;
+    Ok(is_magic_value(&return_data))
+}
+
+async fn simulate_counterfactual<M: Middleware>(provider: &M, signer: Address, hash: H256, wrapped: &Erc6492Signature) -> Result<bool> {
+    let params = json!([{
+        "blockStateCalls": [{
+            "calls": [
+                { "to": wrapped.factory, "data": wrapped.factory_calldata },
+                { "to": signer, "data": is_valid_signature_calldata(hash, &wrapped.signature) },
+            ]
+        }]
+    }, "latest"]);
+    let blocks: Vec<SimulatedBlock> = provider.provider().request("eth_simulateV1", params).await
+        .map_err(|e| anyhow!("Simulating deployment of {:#x} failed: {}", signer, e))?;
+
+    let calls = blocks.first().map(|block| block.calls.as_slice()).unwrap_or_default();
+    match calls {
+        [deployment, check] => Ok(deployment.status == U64::one()
+            && check.status == U64::one()
+            && is_magic_value(&check.return_data)),
+        _ => Err(anyhow!("eth_simulateV1 returned {} calls, expected 2", calls.len())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use ethers::utils::{hash_message, hex};
+
+    #[test]
+    fn test_parse_erc6492() {
+        assert_eq!(parse_erc6492(&[0x01; 65]).unwrap(), None);
+
+        let factory = Address::from_low_u64_be(0xfac);
+        let wrapped = [
+            abi::encode(&[Token::Address(factory), Token::Bytes(vec![0xde, 0xad]), Token::Bytes(vec![0x01; 65])]),
+            ERC6492_MAGIC_SUFFIX.to_vec(),
+        ].concat();
+        assert_eq!(parse_erc6492(&wrapped).unwrap(), Some(Erc6492Signature {
+            factory,
+            factory_calldata: vec![0xde, 0xad].into(),
+            signature: vec![0x01; 65].into(),
+        }));
+
+        assert!(parse_erc6492(&[&[0x01; 3][..], &ERC6492_MAGIC_SUFFIX].concat()).is_err());
+    }
+
+    #[test]
+    fn test_is_valid_signature_calldata() {
+        let calldata = is_valid_signature_calldata(H256::repeat_byte(0xaa), &[0x01, 0x02]);
+        assert_eq!(calldata[..4], EIP1271_MAGIC_VALUE);
+        // selector, hash, offset, length, padded signature
+        assert_eq!(calldata.len(), 4 + 32 * 4);
+        assert_eq!(calldata[4..36], [0xaa; 32]);
+
+        assert!(is_magic_value(&[&EIP1271_MAGIC_VALUE[..], &[0; 28]].concat()));
+        assert!(!is_magic_value(&[0; 32]));
+        assert!(!is_magic_value(&EIP1271_MAGIC_VALUE));
+    }
+
+    #[test]
+    fn test_simulated_block_deserialization() {
+        // Response of eth_simulateV1 for a factory call followed by isValidSignature
+        let response = r#"[{
+            "baseFeePerGas": "0x0",
+            "blobGasUsed": "0x0",
+            "calls": [
+                {
+                    "returnData": "0x",
+                    "logs": [],
+                    "gasUsed": "0x1a2c5",
+                    "status": "0x1"
+                },
+                {
+                    "returnData": "0x1626ba7e00000000000000000000000000000000000000000000000000000000",
+                    "logs": [],
+                    "gasUsed": "0x5f3b",
+                    "status": "0x1"
+                },
+                {
+                    "returnData": "0x",
+                    "logs": [],
+                    "gasUsed": "0x5208",
+                    "status": "0x0",
+                    "error": { "code": -32000, "message": "execution reverted" }
+                }
+            ],
+            "difficulty": "0x0",
+            "excessBlobGas": "0x0",
+            "extraData": "0x",
+            "gasLimit": "0x1c9c380",
+            "gasUsed": "0x20200",
+            "hash": "0x3b29b41bc8a6c8f1a9c0f4d0b4a21c8e8b4e6ab6a4c0c9bb8a37d7f4bb6a6e8c",
+            "miner": "0x0000000000000000000000000000000000000000",
+            "number": "0x12a05f3",
+            "parentHash": "0x7f3c1f5e3b1c1d0a3f1a1e0c9d8b7a6f5e4d3c2b1a0f9e8d7c6b5a4f3e2d1c0b",
+            "timestamp": "0x670f5a3c"
+        }]"#;
+
+        let blocks: Vec<SimulatedBlock> = serde_json::from_str(response).unwrap();
+        let calls = &blocks[0].calls;
+        assert_eq!(calls.len(), 3);
+        assert_eq!(calls[0].status, U64::one());
+        assert!(calls[0].return_data.is_empty());
+        assert!(is_magic_value(&calls[1].return_data));
+        assert_eq!(calls[2].status, U64::zero());
+    }
+
+    /// Init code deploying `runtime`, at most 255 bytes long
+    fn init_code(runtime: &[u8]) -> Vec<u8> {
+        // CODECOPY the runtime appended after these 11 bytes and RETURN it
+        let len = runtime.len() as u8;
+        [&[0x60, len, 0x80, 0x60, 0x0b, 0x60, 0x00, 0x39, 0x60, 0x00, 0xf3][..], runtime].concat()
+    }
+
+    /// Needs a local anvil node: `anvil`, then `cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_verify_contract_signature_on_anvil() {
+        let url = std::env::var("ANVIL_RPC").unwrap_or_else(|_| "http://127.0.0.1:8545".to_string());
+        let provider = Provider::<Http>::try_from(url).unwrap();
+        let hash = hash_message("7346290155");
+        // Wallets whose isValidSignature returns the magic value or zero for anything
+        let accepting = Address::from_str("0x0000000000000000000000000000000000001271").unwrap();
+        let rejecting = Address::from_str("0x0000000000000000000000000000000000001272").unwrap();
+        let set_code = |address: Address, code: &str| {
+            let provider = provider.clone();
+            let code = code.to_string();
+            async move { provider.request::<_, ()>("anvil_setCode", (address, code)).await.unwrap() }
+        };
+        let accepting_code = format!("7f1626ba7e{}60005260206000f3", "00".repeat(28));
+        let rejecting_code = "60206000f3";
+        set_code(accepting, &format!("0x{}", accepting_code)).await;
+        set_code(rejecting, &format!("0x{}", rejecting_code)).await;
+
+        assert!(verify_contract_signature(&provider, accepting, hash, &[0x01; 100]).await.unwrap());
+        assert!(!verify_contract_signature(&provider, rejecting, hash, &[0x01; 100]).await.unwrap());
+        // No code and no ERC-6492 wrapping
+        let eoa = Address::from_low_u64_be(0xe0a);
+        assert!(!verify_contract_signature(&provider, eoa, hash, &[0x01; 65]).await.unwrap());
+
+        // Factory that CREATE2s its calldata as init code with a zero salt
+        let factory = Address::from_str("0x0000000000000000000000000000000000006492").unwrap();
+        set_code(factory, "0x36600060003760003660006000f500").await;
+        let counterfactual = |runtime: &str| {
+            let init_code = init_code(&hex::decode(runtime).unwrap());
+            let signer = ethers::utils::get_create2_address(factory, [0u8; 32], init_code.clone());
+            let wrapped = [
+                abi::encode(&[Token::Address(factory), Token::Bytes(init_code), Token::Bytes(vec![0x01; 65])]),
+                ERC6492_MAGIC_SUFFIX.to_vec(),
+            ].concat();
+            (signer, wrapped)
+        };
+
+        // Not deployed, the deployment is simulated before the check
+        let (signer, wrapped) = counterfactual(&accepting_code);
+        assert!(provider.get_code(signer, None).await.unwrap().is_empty());
+        assert!(verify_contract_signature(&provider, signer, hash, &wrapped).await.unwrap());
+        let (signer, wrapped) = counterfactual(rejecting_code);
+        assert!(!verify_contract_signature(&provider, signer, hash, &wrapped).await.unwrap());
+    }
+}
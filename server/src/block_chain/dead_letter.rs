@@ -0,0 +1,77 @@
+use std::time::Duration;
+use anyhow::Result;
+use sqlx::PgPool;
+
+use crate::block_chain::trade::{IndexedTrade, commit_batch};
+use crate::db::models::{FailedEvent, TradeEventDetails, TradeEventKey};
+use crate::db::operations::{get_due_failed_events, is_trade_event_recorded, resolve_failed_event};
+
+/// Failed attempts after which an event is marked dead and only replayed manually
+pub const MAX_ATTEMPTS: i32 = 10;
+/// How often due events are retried
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+/// Events retried per pass
+const RETRY_BATCH_SIZE: i64 = 50;
+
+impl From<&FailedEvent> for IndexedTrade {
+    fn from(event: &FailedEvent) -> Self {
+        IndexedTrade {
+            key: TradeEventKey {
+                tx_hash: event.tx_hash.clone(),
+                log_index: event.log_index,
+                block_number: event.block_number,
+            },
+            trader: event.trader.clone(),
+            subject: event.subject.clone(),
+            is_buy: event.is_buy,
+            share_amount: event.share_amount.clone(),
+            deployment: event.deployment.clone(),
+            details: TradeEventDetails {
+                price: event.price.clone(),
+                protocol_fee: event.protocol_fee.clone(),
+                subject_fee: event.subject_fee.clone(),
+                supply: event.supply.clone(),
+                timestamp_ms: event.event_timestamp_ms,
+                block_hash: event.block_hash.clone(),
+                checkpoint: event.checkpoint,
+                raw: event.raw_event.clone(),
+            },
+        }
+    }
+}
+
+/// Apply a dead-lettered event again, returns whether it is now in the ledger.
+/// Another failure counts as an attempt and pushes the next retry back.
+pub async fn retry_failed_event(pool: &PgPool, event: &FailedEvent) -> Result<bool> {
+    commit_batch(pool, &event.chain_type, &[IndexedTrade::from(event)], None).await?;
+
+    if !is_trade_event_recorded(pool, &event.chain_type, &event.tx_hash, event.log_index).await? {
+        return Ok(false);
+    }
+    resolve_failed_event(pool, event.id).await?;
+    Ok(true)
+}
+
+/// Retry dead-lettered events once their backoff has elapsed
+pub async fn run_failed_event_retries(pool: PgPool) {
+    let mut ticker = tokio::time::interval(RETRY_INTERVAL);
+
+    loop {
+        ticker.tick().await;
+        let events = match get_due_failed_events(&pool, RETRY_BATCH_SIZE).await {
+            Ok(events) => events,
+            Err(e) => {
+                println!("Failed to get due dead-lettered events: {:?}", e);
+                continue;
+            }
+        };
+
+        for event in events {
+            match retry_failed_event(&pool, &event).await {
+                Ok(true) => println!("Applied dead-lettered {} trade event {}:{}", event.chain_type, event.tx_hash, event.log_index),
+                Ok(false) => println!("Dead-lettered {} trade event {}:{} failed again (attempt {})", event.chain_type, event.tx_hash, event.log_index, event.attempts + 1),
+                Err(e) => println!("Failed to retry dead-lettered event {}: {:?}", event.id, e),
+            }
+        }
+    }
+}
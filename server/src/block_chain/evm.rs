@@ -0,0 +1,520 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use ethers::abi::RawLog;
+use ethers::prelude::*;
+use ethers::utils::{hash_message, hex};
+use futures::StreamExt;
+use sqlx::types::BigDecimal;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use tokio::sync::Notify;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+
+use crate::block_chain::Blockchain;
+use crate::block_chain::rpc::RpcTransport;
+use crate::block_chain::contract_signature::verify_contract_signature;
+use crate::block_chain::siwe::{SiweMessage, recover_signer};
+use crate::block_chain::block_range::{BlockRangeSizer, INITIAL_BLOCK_RANGE, is_range_too_large};
+use crate::block_chain::trade::{IndexedTrade, SyncCursor, commit_batch};
+use crate::block_chain::utils::{TradeEvent, ABI};
+use crate::db::models::{TradeEventDetails, TradeEventKey};
+use crate::db::operations::{discard_failed_event, get_indexed_block_hash, get_indexed_blocks_desc, get_last_synced_block, revert_trade_event, rollback_trades_after_block, update_last_synced_block};
+use crate::config::EvmNetworkConfig;
+use crate::AppConfig;
+
+/// Poll interval once caught up while the log subscription is live
+const STREAMING_POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// Poll interval once caught up without a log subscription
+const FALLBACK_POLL_INTERVAL: Duration = Duration::from_secs(10);
+/// Delay before reconnecting a dropped log subscription
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Shares contract indexer for one EVM network, e.g. Monad
+pub struct EvmBlockchain {
+    network: EvmNetworkConfig,
+    provider: Arc<Provider<RpcTransport>>,
+    contract_addresses: Vec<Address>,
+    /// Whether the log subscription is currently connected
+    streaming: AtomicBool,
+    /// Wakes the polling loop to backfill right away
+    catch_up: Notify,
+    config: Arc<AppConfig>,
+}
+
+impl EvmBlockchain {
+    pub fn new(network: EvmNetworkConfig, config: Arc<AppConfig>) -> Self {
+        let transport = RpcTransport::new(&network.name, &network.rpc, config.rpc_requests_per_second)
+            .expect("Failed to create blockchain RPC client");
+        let provider = Arc::new(Provider::new(transport));
+        
+        let contract_addresses = network.contracts.iter()
+            .map(|contract| Address::from_str(contract)
+                .unwrap_or_else(|e| panic!("Invalid contract address {} for {}: {}", contract, network.name, e)))
+            .collect();
+        
+        Self {
+            network,
+            provider,
+            contract_addresses,
+            streaming: AtomicBool::new(false),
+            catch_up: Notify::new(),
+            config,
+        }
+    }
+    
+    /// Filter matching Trade logs of the shares contracts
+    fn trade_filter(&self) -> Filter {
+        Filter::new()
+            .address(self.contract_addresses.clone())
+            .topic0(TradeEvent::signature())
+    }
+    
+    /// Query Trade logs of a block range
+    async fn query_trades(&self, from_block: u64, to_block: u64) -> Result<Vec<IndexedTrade>> {
+        let filter = self.trade_filter()
+            .from_block(from_block)
+            .to_block(to_block);
+        
+        let logs = self.provider.get_logs(&filter).await?;
+        let mut trades = logs.iter().map(|log| self.decode_trade_log(log)).collect::<Result<Vec<_>>>()?;
+        self.fill_block_timestamps(&mut trades).await?;
+        Ok(trades)
+    }
+    
+    /// Decode a raw Trade log
+    fn decode_trade_log(&self, log: &Log) -> Result<IndexedTrade> {
+        let event = TradeEvent::decode_log(&RawLog::from(log.clone()))?;
+        self.to_indexed_trade(&event, log)
+    }
+    
+    /// Normalize a Trade log into a chain-agnostic trade, the block timestamp is filled in afterwards
+    fn to_indexed_trade(&self, event: &TradeEvent, log: &Log) -> Result<IndexedTrade> {
+        let meta = LogMeta::from(log);
+        Ok(IndexedTrade {
+            key: TradeEventKey {
+                tx_hash: format!("{:#x}", meta.transaction_hash),
+                log_index: meta.log_index.as_u64() as i64,
+                block_number: Some(meta.block_number.as_u64() as i64),
+            },
+            trader: hex::encode(event.trader.as_bytes()),
+            subject: hex::encode(event.subject.as_bytes()),
+            is_buy: event.is_buy,
+            share_amount: to_big_decimal(event.share_amount)?,
+            deployment: format!("{:#x}", log.address),
+            details: TradeEventDetails {
+                price: to_big_decimal(event.eth_amount)?,
+                protocol_fee: to_big_decimal(event.protocol_eth_amount)?,
+                subject_fee: to_big_decimal(event.subject_eth_amount)?,
+                supply: to_big_decimal(event.supply)?,
+                timestamp_ms: None,
+                block_hash: Some(format!("{:#x}", meta.block_hash)),
+                checkpoint: None,
+                raw: serde_json::to_value(log)?,
+            },
+        })
+    }
+    
+    /// Fill in the timestamp of the block each trade was emitted in, fetching every block once
+    async fn fill_block_timestamps(&self, trades: &mut [IndexedTrade]) -> Result<()> {
+        let mut timestamps: HashMap<i64, i64> = HashMap::new();
+        
+        for trade in trades.iter_mut() {
+            let Some(block_number) = trade.key.block_number else {
+                continue;
+            };
+            let timestamp_ms = match timestamps.get(&block_number) {
+                Some(timestamp_ms) => *timestamp_ms,
+                None => {
+                    let block = self.provider.get_block(block_number as u64).await?
+                        .ok_or_else(|| anyhow!("Block {} not found", block_number))?;
+                    let timestamp_ms = block.timestamp.as_u64() as i64 * 1000;
+                    timestamps.insert(block_number, timestamp_ms);
+                    timestamp_ms
+                }
+            };
+            trade.details.timestamp_ms = Some(timestamp_ms);
+        }
+        
+        Ok(())
+    }
+    
+    /// Check that the block after `last_synced_block` builds on the block we indexed,
+    /// returns the common ancestor to roll back to when its parent hash does not match
+    async fn detect_reorg(&self, pool: &PgPool, last_synced_block: u64) -> Result<Option<u64>> {
+        let indexed_hash = match get_indexed_block_hash(pool, self.get_name(), last_synced_block).await? {
+            Some(hash) => hash,
+            None => return Ok(None),
+        };
+        
+        let next_block = self.provider.get_block(last_synced_block + 1).await?
+            .ok_or_else(|| anyhow!("Block {} not found", last_synced_block + 1))?;
+        let parent_hash = format!("{:#x}", next_block.parent_hash);
+        if parent_hash == indexed_hash {
+            return Ok(None);
+        }
+        
+        println!("Reorg detected for {}: parent of block {} is {}, indexed {}", self.get_name(), last_synced_block + 1, parent_hash, indexed_hash);
+        Ok(Some(self.find_common_ancestor(pool, last_synced_block).await?))
+    }
+    
+    /// Find the newest indexed block that is still part of the canonical chain
+    async fn find_common_ancestor(&self, pool: &PgPool, last_synced_block: u64) -> Result<u64> {
+        let indexed_blocks = get_indexed_blocks_desc(pool, self.get_name(), last_synced_block).await?;
+        
+        for (number, indexed_hash) in &indexed_blocks {
+            let canonical_hash = self.provider.get_block(*number).await?
+                .and_then(|block| block.hash)
+                .map(|hash| format!("{:#x}", hash));
+            if canonical_hash.as_deref() == Some(indexed_hash.as_str()) {
+                return Ok(*number);
+            }
+        }
+        
+        // None of the tracked blocks survived, roll back past the whole window
+        let oldest = indexed_blocks.last().map(|(number, _)| *number).unwrap_or(last_synced_block);
+        Ok(std::cmp::max(oldest.saturating_sub(1), self.network.start_block))
+    }
+    
+    /// Revert trades derived from orphaned blocks and move the cursor back to `ancestor`
+    async fn rollback_to_block(&self, pool: &PgPool, ancestor: u64) -> Result<()> {
+        let mut tx = pool.begin().await?;
+        let rolled_back = rollback_trades_after_block(&mut tx, self.get_name(), ancestor).await?;
+        update_last_synced_block(&mut tx, ancestor, self.get_name()).await?;
+        tx.commit().await?;
+        
+        println!("Rolled back {} trade events after block {} for {}", rolled_back, ancestor, self.get_name());
+        Ok(())
+    }
+    
+    /// Refuse to index a node serving a different chain than the network is configured for
+    async fn check_chain_id(&self) -> Result<()> {
+        let chain_id = self.provider.get_chainid().await?;
+        if chain_id != U256::from(self.network.chain_id) {
+            return Err(anyhow!("RPC of {} serves chain id {}, expected {}", self.get_name(), chain_id, self.network.chain_id));
+        }
+        Ok(())
+    }
+    
+    /// Sync confirmed block ranges with `eth_getLogs`, advancing the persisted cursor
+    async fn sync_block_ranges(&self, pool: &PgPool) -> Result<()> {
+        let provider = self.provider.clone();
+        
+        // Get the last synced block number
+        let mut last_synced_block = get_last_synced_block(pool, self.network.start_block, self.get_name()).await?;
+        
+        println!("Starting sync from block {} for {}", last_synced_block, self.get_name());
+        
+        // Blocks per log query, adapted to how dense the range is and to node limits
+        let mut block_range = BlockRangeSizer::new(
+            INITIAL_BLOCK_RANGE,
+            self.config.chain_min_block_range,
+            self.config.chain_max_block_range,
+        );
+        
+        loop {
+            // Get the current chain's latest block
+            let current_block = match provider.get_block_number().await {
+                Ok(block) => block.as_u64(),
+                Err(e) => {
+                    println!("Failed to get current block number: {:?}", e);
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    continue;
+                }
+            };
+            
+            // Only index blocks buried under enough confirmations
+            let safe_block = current_block.saturating_sub(self.network.confirmations);
+            
+            if last_synced_block >= safe_block {
+                // Already synced to the latest confirmed block, wait for a while before continuing
+                println!("Synced to block {} (head {}) for {}, waiting for new blocks...", safe_block, current_block, self.get_name());
+                self.wait_for_new_blocks().await;
+                continue;
+            }
+            
+            // Make sure the chain still builds on the last block we indexed
+            match self.detect_reorg(pool, last_synced_block).await {
+                Ok(Some(ancestor)) => {
+                    match self.rollback_to_block(pool, ancestor).await {
+                        Ok(()) => last_synced_block = ancestor,
+                        Err(e) => {
+                            println!("Failed to roll back to block {}: {:?}", ancestor, e);
+                            tokio::time::sleep(Duration::from_secs(10)).await;
+                        }
+                    }
+                    continue;
+                },
+                Ok(None) => {},
+                Err(e) => {
+                    println!("Failed to check for reorg at block {}: {:?}", last_synced_block, e);
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    continue;
+                }
+            }
+            
+            // Calculate the end block for this sync
+            let end_block = std::cmp::min(last_synced_block + block_range.size(), safe_block);
+            
+            // The end block hash is stored with the batch, so the next range can be checked against it
+            let end_header = match provider.get_block(end_block).await {
+                Ok(Some(block)) => block,
+                Ok(None) => {
+                    println!("Block {} not found", end_block);
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    continue;
+                },
+                Err(e) => {
+                    println!("Failed to get block {}: {:?}", end_block, e);
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    continue;
+                }
+            };
+            let cursor = SyncCursor::BlockWithHash {
+                range_start: last_synced_block,
+                number: end_block,
+                hash: format!("{:#x}", end_header.hash.unwrap_or_default()),
+                parent_hash: format!("{:#x}", end_header.parent_hash),
+            };
+            
+            println!("Syncing blocks {} to {} for {}", last_synced_block, end_block, self.get_name());
+            
+            // Query events
+            match self.query_trades(last_synced_block, end_block).await {
+                Ok(trades) => {
+                    println!("Found {} events in blocks {} to {} for {}", trades.len(), last_synced_block, end_block, self.get_name());
+                    block_range.record_success(trades.len());
+                    
+                    // Apply all events and advance the cursor atomically, retry the whole range on failure
+                    match commit_batch(pool, self.get_name(), &trades, Some(cursor)).await {
+                        Ok(()) => {
+                            last_synced_block = end_block;
+                        },
+                        Err(e) => {
+                            println!("Failed to commit blocks {} to {}, will retry: {:?}", last_synced_block, end_block, e);
+                            tokio::time::sleep(Duration::from_secs(10)).await;
+                        }
+                    }
+                },
+                Err(e) if is_range_too_large(&e) && block_range.shrink() => {
+                    // Split the range and retry right away
+                    println!("Blocks {} to {} rejected as too large, retrying with {} blocks: {:#}", last_synced_block, end_block, block_range.size(), e);
+                    continue;
+                },
+                Err(e) => {
+                    println!("Failed to query events: {:?}", e);
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                }
+            }
+            
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+    
+    /// Wait before polling again once caught up, a (re)connected subscription cuts the wait short
+    async fn wait_for_new_blocks(&self) {
+        let interval = if self.streaming.load(Ordering::Relaxed) {
+            STREAMING_POLL_INTERVAL
+        } else {
+            FALLBACK_POLL_INTERVAL
+        };
+        
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {},
+            _ = self.catch_up.notified() => {},
+        }
+    }
+    
+    /// Apply Trade logs as soon as they are mined via `eth_subscribe("logs")`, reconnecting forever.
+    /// Streamed events do not move the cursor, the polling loop confirms them later.
+    async fn stream_logs(&self, pool: &PgPool, ws_url: &str) {
+        loop {
+            match Provider::<Ws>::connect(ws_url).await {
+                Ok(ws_provider) => match ws_provider.subscribe_logs(&self.trade_filter()).await {
+                    Ok(mut stream) => {
+                        println!("Subscribed to Trade logs for {}", self.get_name());
+                        self.streaming.store(true, Ordering::Relaxed);
+                        
+                        // Fill the gap left while the subscription was down
+                        self.catch_up.notify_one();
+                        if let Err(e) = self.backfill_unconfirmed(pool).await {
+                            println!("Failed to backfill unconfirmed blocks for {}: {:?}", self.get_name(), e);
+                        }
+                        
+                        while let Some(log) = stream.next().await {
+                            if let Err(e) = self.apply_streamed_log(pool, &log).await {
+                                println!("Error processing streamed trade log: {:?}", e);
+                            }
+                        }
+                        println!("Trade log subscription closed for {}", self.get_name());
+                    },
+                    Err(e) => println!("Failed to subscribe to Trade logs: {:?}", e),
+                },
+                Err(e) => println!("Failed to connect to {}: {:?}", ws_url, e),
+            }
+            
+            // Fall back to polling until the subscription is back
+            self.streaming.store(false, Ordering::Relaxed);
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+    
+    /// Apply Trade logs of the unconfirmed blocks without moving the cursor,
+    /// confirmed blocks are left to the polling loop
+    async fn backfill_unconfirmed(&self, pool: &PgPool) -> Result<()> {
+        let last_synced_block = get_last_synced_block(pool, self.network.start_block, self.get_name()).await?;
+        let to_block = self.provider.get_block_number().await?.as_u64();
+        let from_block = std::cmp::max(last_synced_block, to_block.saturating_sub(self.network.confirmations));
+        if from_block >= to_block {
+            return Ok(());
+        }
+        
+        let trades = self.query_trades(from_block, to_block).await?;
+        println!("Backfilled {} events in blocks {} to {} for {}", trades.len(), from_block, to_block, self.get_name());
+        
+        commit_batch(pool, self.get_name(), &trades, None).await?;
+        Ok(())
+    }
+    
+    /// Apply a log from the subscription, or revert it when the node reports it removed by a reorg
+    async fn apply_streamed_log(&self, pool: &PgPool, log: &Log) -> Result<()> {
+        if log.removed == Some(true) {
+            let tx_hash = log.transaction_hash.ok_or_else(|| anyhow!("Removed log without transaction hash"))?;
+            let log_index = log.log_index.ok_or_else(|| anyhow!("Removed log without log index"))?;
+            
+            let mut tx = pool.begin().await?;
+            let tx_hash = format!("{:#x}", tx_hash);
+            let log_index = log_index.as_u64() as i64;
+            if revert_trade_event(&mut tx, self.get_name(), &tx_hash, log_index).await? {
+                println!("Reverted removed trade log {}:{}", tx_hash, log_index);
+            }
+            discard_failed_event(&mut tx, self.get_name(), &tx_hash, log_index).await?;
+            tx.commit().await?;
+            return Ok(());
+        }
+        
+        let mut trades = [self.decode_trade_log(log)?];
+        self.fill_block_timestamps(&mut trades).await?;
+        commit_batch(pool, self.get_name(), &trades, None).await?;
+        Ok(())
+    }
+}
+
+/// Convert an event amount to the NUMERIC representation stored in the database
+fn to_big_decimal(value: U256) -> Result<BigDecimal> {
+    Ok(BigDecimal::from_str(&value.to_string())?)
+}
+
+#[async_trait]
+impl Blockchain for EvmBlockchain {
+    fn get_name(&self) -> &str {
+        &self.network.name
+    }
+    
+    fn deployments(&self) -> Vec<String> {
+        self.contract_addresses.iter().map(|address| format!("{:#x}", address)).collect()
+    }
+    
+    async fn sync_events(&self, pool: &PgPool) -> Result<()> {
+        self.check_chain_id().await?;
+        
+        match &self.network.ws_rpc {
+            Some(ws_url) => {
+                // Stream new logs for low latency, polling keeps confirming ranges and covers outages.
+                // The stream reconnects forever, so return as soon as polling stops with an error
+                tokio::select! {
+                    polled = self.sync_block_ranges(pool) => polled,
+                    _ = self.stream_logs(pool, ws_url) => Err(anyhow!("Trade log stream of {} stopped", self.get_name())),
+                }
+            },
+            None => self.sync_block_ranges(pool).await,
+        }
+    }
+    
+    async fn backfill_range(&self, pool: &PgPool, from: u64, to: u64) -> Result<usize> {
+        let mut block_range = BlockRangeSizer::new(
+            INITIAL_BLOCK_RANGE,
+            self.config.chain_min_block_range,
+            self.config.chain_max_block_range,
+        );
+        let mut found = 0;
+        let mut start = from;
+        
+        while start <= to {
+            let end = std::cmp::min(start + block_range.size(), to);
+            let trades = match self.query_trades(start, end).await {
+                Ok(trades) => trades,
+                Err(e) if is_range_too_large(&e) && block_range.shrink() => continue,
+                Err(e) => return Err(e),
+            };
+            block_range.record_success(trades.len());
+            println!("Backfilling {} events in blocks {} to {} for {}", trades.len(), start, end, self.get_name());
+            
+            commit_batch(pool, self.get_name(), &trades, None).await?;
+            found += trades.len();
+            start = end + 1;
+        }
+        
+        Ok(found)
+    }
+    
+    async fn verify_signature(&self, challenge: &str, signature: &str) -> Result<String, String> {
+        // The challenge is a Sign-In with Ethereum message, bound to this server and network
+        let message = SiweMessage::from_str(challenge)
+            .map_err(|e| format!("Invalid SIWE message: {}", e))?;
+        let (Some(domain), Some(uri)) = (&self.config.siwe_domain, &self.config.siwe_uri) else {
+            return Err("Sign-In with Ethereum is not configured".to_string());
+        };
+        message.validate(domain, uri, self.network.chain_id, OffsetDateTime::now_utc())
+            .map_err(|e| format!("SIWE message rejected: {}", e))?;
+        
+        // Accounts controlled by a key sign with it, recovery alone proves ownership
+        if let Ok(recovered_address) = recover_signer(challenge, signature) {
+            if recovered_address == message.address {
+                return Ok(hex::encode(recovered_address.as_bytes()));
+            }
+        }
+        
+        // Contract wallets such as Safe check their signatures themselves, deployed or not
+        let sig_bytes = hex::decode(signature)
+            .map_err(|e| format!("Invalid signature hex: {}", e))?;
+        match verify_contract_signature(self.provider.as_ref(), message.address, hash_message(challenge), &sig_bytes).await {
+            Ok(true) => Ok(hex::encode(message.address.as_bytes())),
+            Ok(false) => Err(format!("Signature is not valid for {:#x}", message.address)),
+            Err(e) => Err(format!("Contract wallet signature check failed: {:?}", e)),
+        }
+    }
+    
+    async fn get_shares_balance(&self, deployment: &str, subject: &str, user: &str) -> Result<u64> {
+        let contract_address = Address::from_str(deployment).map_err(|e| anyhow!("Invalid deployment address: {}", e))?;
+        if !self.contract_addresses.contains(&contract_address) {
+            return Err(anyhow!("Deployment {} is not indexed on {}", deployment, self.get_name()));
+        }
+        let subject_address = Address::from_str(subject).map_err(|e| anyhow!("Invalid subject address: {}", e))?;
+        let user_address = Address::from_str(user).map_err(|e| anyhow!("Invalid user address: {}", e))?;
+        
+        let abi: ethers::abi::Abi = serde_json::from_str(ABI).expect("Invalid abi");
+        let contract = ethers::contract::Contract::new(
+            contract_address,
+            abi,
+            self.provider.clone()
+        );
+
+        let balance: U256 = contract
+            .method::<_, U256>("sharesBalance", (subject_address, user_address))
+            .map_err(|e| anyhow!("Failed to get sharesBalance method: {}", e))?
+            .call()
+            .await
+            .map_err(|e| anyhow!("Failed to call sharesBalance: {}", e))?;
+            
+        u64::try_from(balance).map_err(|_| anyhow!("sharesBalance {} does not fit in u64", balance))
+    }
+    
+    async fn get_current_supply(&self, _deployment: &str, _subject: &str) -> Result<Option<u64>> {
+        // The indexed ABI has no supply view, supply is only known from Trade events
+        Ok(None)
+    }
+}
@@ -1,33 +1,43 @@
-pub mod monad;
+pub mod block_range;
+pub mod contract_signature;
+pub mod dead_letter;
+pub mod evm;
+pub mod outbox;
+pub mod registry;
+pub mod rpc;
+pub mod siwe;
 pub mod utils;
 pub mod sui;
+pub mod supply;
+pub mod trade;
 
 use anyhow::Result;
 use sqlx::PgPool;
-use std::sync::Arc;
 use async_trait::async_trait;
 
 /// Blockchain interface abstraction
 #[async_trait]
 pub trait Blockchain: Send + Sync {
     /// Get blockchain name
-    fn get_name(&self) -> &'static str;
+    fn get_name(&self) -> &str;
+    
+    /// Indexed SharesTrading deployments, the first one owns data indexed before deployments were tracked
+    fn deployments(&self) -> Vec<String>;
     
     /// Sync transaction events
     async fn sync_events(&self, pool: &PgPool) -> Result<()>;
     
-    /// Verify user signature
-    fn verify_signature(&self, challenge: &str, signature: &str) -> Result<String, String>;
+    /// Re-fetch Trade events of an inclusive block (EVM) or checkpoint (Sui) range
+    /// and ingest those missing from the ledger, without moving the sync cursor.
+    /// Returns the number of events found on chain.
+    async fn backfill_range(&self, pool: &PgPool, from: u64, to: u64) -> Result<usize>;
+    
+    /// Verify a user's signature of `challenge`, returns the signer address as lower-case hex without 0x prefix
+    async fn verify_signature(&self, challenge: &str, signature: &str) -> Result<String, String>;
     
-    /// Get user's shares balance
-    async fn get_shares_balance(&self, subject: &str, user: &str) -> Result<u64>;
+    /// Get user's shares balance on a deployment
+    async fn get_shares_balance(&self, deployment: &str, subject: &str, user: &str) -> Result<u64>;
+    
+    /// Get a subject's current shares supply on a deployment, `None` when the contract doesn't expose it
+    async fn get_current_supply(&self, deployment: &str, subject: &str) -> Result<Option<u64>>;
 }
-
-// Factory function to create different chain implementations
-pub fn create_blockchain(chain_type: &str, config: Arc<crate::AppConfig>) -> Box<dyn Blockchain> {
-    match chain_type {
-        "monad" => Box::new(monad::MonadBlockchain::new(config)),
-        "sui" => Box::new(sui::SuiBlockchain::new(config)),
-        _ => panic!("Unsupported blockchain type: {}", chain_type),
-    }
-} 
\ No newline at end of file
@@ -0,0 +1,76 @@
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use sqlx::{PgConnection, PgPool};
+
+use crate::block_chain::trade::AccessChange;
+use crate::db::models::TelegramOutboxEntry;
+use crate::db::operations::{enqueue_telegram_action, get_due_telegram_actions, mark_telegram_action_failed, mark_telegram_action_sent};
+
+/// Failed attempts after which a permission change is marked dead
+const MAX_ATTEMPTS: i32 = 8;
+/// How often the outbox is polled for due changes
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Changes executed per poll
+const BATCH_SIZE: i64 = 50;
+
+/// Queue a permission change as part of the caller's transaction. `dedup_key` identifies
+/// what caused it, so re-processing the same event never queues the change twice.
+pub async fn enqueue_access_change(conn: &mut PgConnection, change: &AccessChange, chain_type: &str, dedup_key: &str) -> Result<()> {
+    let (action, agent_name, chat_group_id, telegram_id) = match change {
+        AccessChange::Grant { agent_name, chat_group_id, telegram_id } => ("grant", agent_name, chat_group_id, telegram_id),
+        AccessChange::Revoke { agent_name, chat_group_id, telegram_id } => ("revoke", agent_name, chat_group_id, telegram_id),
+    };
+    enqueue_telegram_action(conn, dedup_key, action, agent_name, chat_group_id, telegram_id, chain_type).await?;
+    Ok(())
+}
+
+impl TryFrom<&TelegramOutboxEntry> for AccessChange {
+    type Error = anyhow::Error;
+
+    fn try_from(entry: &TelegramOutboxEntry) -> Result<Self> {
+        let agent_name = entry.agent_name.clone();
+        let chat_group_id = entry.chat_group_id.clone();
+        let telegram_id = entry.telegram_id.clone();
+        match entry.action.as_str() {
+            "grant" => Ok(AccessChange::Grant { agent_name, chat_group_id, telegram_id }),
+            "revoke" => Ok(AccessChange::Revoke { agent_name, chat_group_id, telegram_id }),
+            action => Err(anyhow!("Unknown outbox action {}", action)),
+        }
+    }
+}
+
+/// Execute queued permission changes against Telegram, retrying failures with backoff
+pub async fn run_outbox_worker(pool: PgPool) {
+    let mut ticker = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        ticker.tick().await;
+        let entries = match get_due_telegram_actions(&pool, BATCH_SIZE).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                println!("Failed to get due Telegram outbox entries: {:?}", e);
+                continue;
+            }
+        };
+
+        for entry in entries {
+            // The token is read from telegram_bots when the change is due, so rotated tokens apply
+            let result = match (AccessChange::try_from(&entry), &entry.bot_token) {
+                (Ok(change), Some(bot_token)) => change.execute(bot_token).await,
+                (Ok(_), None) => Err(anyhow!("Bot {} no longer exists", entry.agent_name)),
+                (Err(e), _) => Err(e),
+            };
+
+            let marked = match result {
+                Ok(()) => mark_telegram_action_sent(&pool, entry.id).await,
+                Err(e) => {
+                    println!("Failed to {} Telegram user {} in {} (attempt {}): {:?}", entry.action, entry.telegram_id, entry.chat_group_id, entry.attempts + 1, e);
+                    mark_telegram_action_failed(&pool, entry.id, &format!("{:#}", e), MAX_ATTEMPTS).await
+                }
+            };
+            if let Err(e) = marked {
+                println!("Failed to update Telegram outbox entry {}: {:?}", entry.id, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use anyhow::{Result, anyhow};
+use sqlx::PgPool;
+
+use crate::block_chain::Blockchain;
+use crate::block_chain::evm::EvmBlockchain;
+use crate::block_chain::sui::SuiBlockchain;
+use crate::db::operations::assign_legacy_deployment;
+use crate::AppConfig;
+
+/// Chain type of the Sui backend
+pub const SUI_CHAIN_TYPE: &str = "sui";
+
+/// Enabled chains, built once at startup and shared by the indexers, workers and routes
+pub struct ChainRegistry {
+    chains: HashMap<String, Arc<dyn Blockchain>>,
+}
+
+impl ChainRegistry {
+    /// Enable the chains listed in `ENABLED_CHAINS`, or every configured chain when it is unset:
+    /// each EVM network and Sui when a deployment is configured
+    pub fn from_config(config: Arc<AppConfig>) -> Result<Self> {
+        let enabled = match &config.enabled_chains {
+            Some(enabled) => enabled.clone(),
+            None => {
+                let mut enabled: Vec<String> = config.evm_networks.iter().map(|network| network.name.clone()).collect();
+                if !config.sui_deployments.is_empty() {
+                    enabled.push(SUI_CHAIN_TYPE.to_string());
+                }
+                enabled
+            }
+        };
+
+        let mut chains: HashMap<String, Arc<dyn Blockchain>> = HashMap::new();
+        for chain_type in enabled {
+            let blockchain: Arc<dyn Blockchain> = if chain_type == SUI_CHAIN_TYPE {
+                Arc::new(SuiBlockchain::new(config.clone()))
+            } else {
+                let network = config.evm_networks.iter()
+                    .find(|network| network.name == chain_type)
+                    .ok_or_else(|| anyhow!("Enabled chain {} is not configured", chain_type))?;
+                // EVM wallets sign in with Ethereum to this site
+                if config.siwe_domain.is_none() || config.siwe_uri.is_none() {
+                    return Err(anyhow!("SIWE_DOMAIN and SIWE_URI must be set to enable EVM chain {}", chain_type));
+                }
+                Arc::new(EvmBlockchain::new(network.clone(), config.clone()))
+            };
+            chains.insert(chain_type, blockchain);
+        }
+
+        Ok(Self { chains })
+    }
+
+    /// Get an enabled chain by its chain type
+    pub fn get(&self, chain_type: &str) -> Option<Arc<dyn Blockchain>> {
+        self.chains.get(chain_type).cloned()
+    }
+
+    /// Get an enabled chain, failing with a readable error for unknown or disabled ones
+    pub fn require(&self, chain_type: &str) -> Result<Arc<dyn Blockchain>> {
+        self.get(chain_type).ok_or_else(|| anyhow!("Chain {} is not enabled", chain_type))
+    }
+
+    /// Chain types of the enabled chains
+    pub fn chain_types(&self) -> Vec<&str> {
+        let mut chain_types: Vec<&str> = self.chains.keys().map(String::as_str).collect();
+        chain_types.sort();
+        chain_types
+    }
+
+    /// Assign rows indexed before deployments were tracked to each chain's first deployment
+    pub async fn assign_legacy_deployments(&self, pool: &PgPool) -> Result<()> {
+        for (chain_type, blockchain) in &self.chains {
+            if let Some(deployment) = blockchain.deployments().first() {
+                assign_legacy_deployment(pool, chain_type, deployment).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sync events of every enabled chain until all of them stop
+    pub async fn sync_all(&self, pool: &PgPool) {
+        let sync_tasks = self.chains.values().map(|blockchain| async move {
+            if let Err(e) = blockchain.sync_events(pool).await {
+                println!("Error syncing {} events: {:?}", blockchain.get_name(), e);
+            }
+        });
+
+        futures::future::join_all(sync_tasks).await;
+    }
+}
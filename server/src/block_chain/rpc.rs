@@ -0,0 +1,317 @@
+use std::fmt::Debug;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use ethers::providers::{JsonRpcClient, JsonRpcError, ProviderError, RpcError};
+use reqwest::{Client, StatusCode};
+use reqwest::header::RETRY_AFTER;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+/// Timeout of a single request to one endpoint
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long idle pooled connections are kept open
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+/// Cooldown of an endpoint answering 429 without a Retry-After header
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+/// Cooldown of an endpoint after a transport failure or a 5xx
+const FAILURE_COOLDOWN: Duration = Duration::from_secs(5);
+/// Weight of the latest request in the latency and error rate moving averages
+const HEALTH_SMOOTHING: f64 = 0.2;
+/// How much a 100% error rate inflates an endpoint's latency score
+const ERROR_RATE_PENALTY: f64 = 10.0;
+
+/// Error of a request sent through `RpcTransport`
+#[derive(Debug, thiserror::Error)]
+pub enum RpcTransportError {
+    /// The node answered with a JSON-RPC error, returned as is without failover
+    #[error(transparent)]
+    JsonRpc(JsonRpcError),
+    #[error("failed to (de)serialize RPC payload: {0}")]
+    Serde(#[from] serde_json::Error),
+    /// Every endpoint failed, holds the last failure
+    #[error("RPC endpoint {url} failed: {reason}")]
+    Endpoint { url: String, reason: String },
+}
+
+impl RpcError for RpcTransportError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            RpcTransportError::JsonRpc(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            RpcTransportError::Serde(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<RpcTransportError> for ProviderError {
+    fn from(e: RpcTransportError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(e))
+    }
+}
+
+/// Client side rate limit of one endpoint
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(requests_per_second: u32) -> Self {
+        let rate = requests_per_second.max(1) as f64;
+        Self {
+            capacity: rate,
+            tokens: rate,
+            refill_per_sec: rate,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Take a token, or return how long to wait until one is available
+    fn try_acquire(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.refill_per_sec))
+        }
+    }
+}
+
+#[derive(Debug)]
+struct EndpointHealth {
+    bucket: TokenBucket,
+    /// Moving average of successful request latency
+    latency_ms: f64,
+    /// Moving average of failed requests, between 0 and 1
+    error_rate: f64,
+    /// Set after a 429 or a failure, the endpoint is only used once the others failed too
+    cooldown_until: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    url: String,
+    health: Mutex<EndpointHealth>,
+}
+
+impl Endpoint {
+    fn new(url: String, requests_per_second: u32) -> Self {
+        Self {
+            url,
+            health: Mutex::new(EndpointHealth {
+                bucket: TokenBucket::new(requests_per_second),
+                latency_ms: 0.0,
+                error_rate: 0.0,
+                cooldown_until: None,
+            }),
+        }
+    }
+
+    /// Ranking key, lower is better: cooling down endpoints go last, then by penalized latency
+    fn score(&self, now: Instant) -> (bool, f64) {
+        let health = self.health.lock().unwrap();
+        let cooling_down = health.cooldown_until.map_or(false, |until| until > now);
+        (cooling_down, health.latency_ms * (1.0 + health.error_rate * ERROR_RATE_PENALTY))
+    }
+
+    /// Wait until the endpoint's rate limit allows another request
+    async fn acquire(&self) {
+        loop {
+            let wait = self.health.lock().unwrap().bucket.try_acquire(Instant::now());
+            match wait {
+                Ok(()) => return,
+                Err(wait) => tokio::time::sleep(wait).await,
+            }
+        }
+    }
+
+    fn record_success(&self, latency: Duration) {
+        let mut health = self.health.lock().unwrap();
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        health.latency_ms = if health.latency_ms == 0.0 {
+            latency_ms
+        } else {
+            health.latency_ms * (1.0 - HEALTH_SMOOTHING) + latency_ms * HEALTH_SMOOTHING
+        };
+        health.error_rate *= 1.0 - HEALTH_SMOOTHING;
+        health.cooldown_until = None;
+    }
+
+    fn record_failure(&self, cooldown: Duration, reason: String) -> RpcTransportError {
+        let mut health = self.health.lock().unwrap();
+        health.error_rate = health.error_rate * (1.0 - HEALTH_SMOOTHING) + HEALTH_SMOOTHING;
+        health.cooldown_until = Some(Instant::now() + cooldown);
+        RpcTransportError::Endpoint { url: self.url.clone(), reason }
+    }
+}
+
+/// JSON-RPC over HTTP across several endpoints of the same chain.
+/// Requests go to the healthiest endpoint and fail over to the next one on
+/// transport errors, 5xx and 429 responses. Connections are pooled.
+#[derive(Debug)]
+pub struct RpcTransport {
+    name: String,
+    client: Client,
+    endpoints: Vec<Endpoint>,
+    next_id: AtomicU64,
+}
+
+impl RpcTransport {
+    /// `requests_per_second` is enforced per endpoint
+    pub fn new(name: &str, urls: &[String], requests_per_second: u32) -> Result<Self> {
+        if urls.is_empty() {
+            return Err(anyhow!("No RPC endpoint configured for {}", name));
+        }
+
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+            .build()?;
+
+        Ok(Self {
+            name: name.to_string(),
+            client,
+            endpoints: urls.iter().map(|url| Endpoint::new(url.clone(), requests_per_second)).collect(),
+            next_id: AtomicU64::new(1),
+        })
+    }
+
+    /// Endpoints from healthiest to least healthy, configuration order breaks ties
+    fn ranked_endpoints(&self) -> Vec<&Endpoint> {
+        let now = Instant::now();
+        let mut ranked: Vec<(&Endpoint, (bool, f64))> = self.endpoints.iter()
+            .map(|endpoint| (endpoint, endpoint.score(now)))
+            .collect();
+        ranked.sort_by(|(_, a), (_, b)| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)));
+        ranked.into_iter().map(|(endpoint, _)| endpoint).collect()
+    }
+
+    /// Send a JSON-RPC request and return its result, failing over across endpoints
+    pub async fn request_value(&self, method: &str, params: Value) -> Result<Value, RpcTransportError> {
+        let mut payload = json!({
+            "jsonrpc": "2.0",
+            "id": self.next_id.fetch_add(1, Ordering::Relaxed),
+            "method": method,
+        });
+        if !params.is_null() {
+            payload["params"] = params;
+        }
+
+        let mut last_error = None;
+        for endpoint in self.ranked_endpoints() {
+            match self.send(endpoint, &payload).await {
+                Ok(response) => return Self::into_result(response),
+                Err(e) => {
+                    println!("{} RPC {} failed, trying next endpoint: {}", self.name, method, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.expect("RpcTransport has at least one endpoint"))
+    }
+
+    /// Send the payload to one endpoint, returning the response body once the node answered
+    async fn send(&self, endpoint: &Endpoint, payload: &Value) -> Result<Value, RpcTransportError> {
+        endpoint.acquire().await;
+        let started = Instant::now();
+
+        let response = match self.client.post(&endpoint.url).json(payload).send().await {
+            Ok(response) => response,
+            Err(e) => return Err(endpoint.record_failure(FAILURE_COOLDOWN, e.to_string())),
+        };
+
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response.headers().get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_RETRY_AFTER);
+            return Err(endpoint.record_failure(retry_after, format!("rate limited, retry after {:?}", retry_after)));
+        }
+        if status.is_server_error() {
+            return Err(endpoint.record_failure(FAILURE_COOLDOWN, format!("HTTP {}", status)));
+        }
+
+        // Some nodes answer JSON-RPC errors with a 4xx status, those are still answers
+        match response.json::<Value>().await {
+            Ok(body) if status.is_success() || body.get("error").is_some() => {
+                endpoint.record_success(started.elapsed());
+                Ok(body)
+            },
+            Ok(_) => Err(endpoint.record_failure(FAILURE_COOLDOWN, format!("HTTP {}", status))),
+            Err(e) => Err(endpoint.record_failure(FAILURE_COOLDOWN, format!("invalid response body: {}", e))),
+        }
+    }
+
+    fn into_result(mut response: Value) -> Result<Value, RpcTransportError> {
+        if let Some(error) = response.get_mut("error").map(Value::take) {
+            return Err(RpcTransportError::JsonRpc(serde_json::from_value(error)?));
+        }
+        Ok(response.get_mut("result").map(Value::take).unwrap_or(Value::Null))
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for RpcTransport {
+    type Error = RpcTransportError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let params = serde_json::to_value(params)?;
+        let result = self.request_value(method, params).await?;
+        Ok(serde_json::from_value(result)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_refills_over_time() {
+        let mut bucket = TokenBucket::new(2);
+        let start = bucket.last_refill;
+
+        assert!(bucket.try_acquire(start).is_ok());
+        assert!(bucket.try_acquire(start).is_ok());
+        let wait = bucket.try_acquire(start).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+
+        assert!(bucket.try_acquire(start + Duration::from_millis(500)).is_ok());
+    }
+
+    #[test]
+    fn test_ranked_endpoints_prefer_healthy() {
+        let urls = vec!["http://a".to_string(), "http://b".to_string(), "http://c".to_string()];
+        let transport = RpcTransport::new("test", &urls, 10).unwrap();
+
+        transport.endpoints[0].record_failure(Duration::from_secs(60), "down".to_string());
+        transport.endpoints[1].record_success(Duration::from_millis(200));
+        transport.endpoints[2].record_success(Duration::from_millis(50));
+
+        let ranked: Vec<&str> = transport.ranked_endpoints().iter().map(|e| e.url.as_str()).collect();
+        assert_eq!(ranked, vec!["http://c", "http://b", "http://a"]);
+    }
+}
@@ -0,0 +1,327 @@
+use std::fmt;
+use std::str::FromStr;
+use ethers::prelude::{Address, Signature};
+use ethers::utils::{hash_message, hex, to_checksum};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+/// First line after the domain of every Sign-In with Ethereum message
+const PREAMBLE: &str = " wants you to sign in with your Ethereum account:";
+/// Only version defined by EIP-4361
+const VERSION: &str = "1";
+
+#[derive(Debug, thiserror::Error)]
+pub enum SiweError {
+    #[error("malformed SIWE message: {0}")]
+    Format(String),
+    #[error("{field} is {actual}, expected {expected}")]
+    Mismatch { field: &'static str, actual: String, expected: String },
+    #[error("message expired at {0}")]
+    Expired(String),
+    #[error("message is not valid before {0}")]
+    NotYetValid(String),
+    #[error("invalid signature: {0}")]
+    Signature(String),
+}
+
+/// Sign-In with Ethereum (EIP-4361) message
+#[derive(Clone, Debug, PartialEq)]
+pub struct SiweMessage {
+    /// RFC 3986 authority of the site requesting the signature
+    pub domain: String,
+    pub address: Address,
+    /// Human-readable assertion shown by the wallet
+    pub statement: Option<String>,
+    pub uri: String,
+    pub chain_id: u64,
+    pub nonce: String,
+    pub issued_at: OffsetDateTime,
+    pub expiration_time: Option<OffsetDateTime>,
+    pub not_before: Option<OffsetDateTime>,
+    pub request_id: Option<String>,
+    pub resources: Vec<String>,
+}
+
+impl SiweMessage {
+    /// Check the message was issued for `domain`, `uri` and `chain_id` and is valid at `now`.
+    /// The nonce is not checked here, messages are looked up by their nonce.
+    pub fn validate(&self, domain: &str, uri: &str, chain_id: u64, now: OffsetDateTime) -> Result<(), SiweError> {
+        if self.domain != domain {
+            return Err(mismatch("domain", &self.domain, domain));
+        }
+        if self.uri != uri {
+            return Err(mismatch("URI", &self.uri, uri));
+        }
+        if self.chain_id != chain_id {
+            return Err(mismatch("chain ID", self.chain_id, chain_id));
+        }
+        if let Some(expiration_time) = self.expiration_time {
+            if now >= expiration_time {
+                return Err(SiweError::Expired(format_time(expiration_time)));
+            }
+        }
+        if let Some(not_before) = self.not_before {
+            if now < not_before {
+                return Err(SiweError::NotYetValid(format_time(not_before)));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn mismatch(field: &'static str, actual: impl fmt::Display, expected: impl fmt::Display) -> SiweError {
+    SiweError::Mismatch { field, actual: actual.to_string(), expected: expected.to_string() }
+}
+
+fn format_time(time: OffsetDateTime) -> String {
+    time.format(&Rfc3339).unwrap_or_else(|_| time.to_string())
+}
+
+fn parse_time(field: &str, value: &str) -> Result<OffsetDateTime, SiweError> {
+    OffsetDateTime::parse(value, &Rfc3339)
+        .map_err(|e| SiweError::Format(format!("{} {} is not RFC 3339: {}", field, value, e)))
+}
+
+impl fmt::Display for SiweMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}{}", self.domain, PREAMBLE)?;
+        writeln!(f, "{}", to_checksum(&self.address, None))?;
+        writeln!(f)?;
+        if let Some(statement) = &self.statement {
+            writeln!(f, "{}", statement)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "URI: {}", self.uri)?;
+        writeln!(f, "Version: {}", VERSION)?;
+        writeln!(f, "Chain ID: {}", self.chain_id)?;
+        writeln!(f, "Nonce: {}", self.nonce)?;
+        write!(f, "Issued At: {}", format_time(self.issued_at))?;
+        if let Some(expiration_time) = self.expiration_time {
+            write!(f, "\nExpiration Time: {}", format_time(expiration_time))?;
+        }
+        if let Some(not_before) = self.not_before {
+            write!(f, "\nNot Before: {}", format_time(not_before))?;
+        }
+        if let Some(request_id) = &self.request_id {
+            write!(f, "\nRequest ID: {}", request_id)?;
+        }
+        if !self.resources.is_empty() {
+            write!(f, "\nResources:")?;
+            for resource in &self.resources {
+                write!(f, "\n- {}", resource)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SiweMessage {
+    type Err = SiweError;
+
+    fn from_str(message: &str) -> Result<Self, Self::Err> {
+        let mut lines = message.split('\n').peekable();
+        let mut next_line = |expected: &str| lines.next()
+            .ok_or_else(|| SiweError::Format(format!("missing {}", expected)));
+
+        let domain = next_line("preamble")?.strip_suffix(PREAMBLE)
+            .ok_or_else(|| SiweError::Format("missing preamble".to_string()))?
+            .to_string();
+        if domain.is_empty() || domain.contains("://") {
+            return Err(SiweError::Format(format!("invalid domain {}", domain)));
+        }
+
+        let address_line = next_line("address")?;
+        let address = Address::from_str(address_line)
+            .map_err(|e| SiweError::Format(format!("invalid address {}: {}", address_line, e)))?;
+        // EIP-55 checksum, so a mistyped address is noticed
+        if to_checksum(&address, None) != address_line {
+            return Err(SiweError::Format(format!("address {} is not checksummed", address_line)));
+        }
+
+        if !next_line("blank line")?.is_empty() {
+            return Err(SiweError::Format("missing blank line after address".to_string()));
+        }
+        let statement = match next_line("statement")? {
+            "" => None,
+            statement => {
+                if !next_line("blank line")?.is_empty() {
+                    return Err(SiweError::Format("missing blank line after statement".to_string()));
+                }
+                Some(statement.to_string())
+            }
+        };
+
+        let mut tagged = |tag: &str| -> Result<String, SiweError> {
+            next_line(tag)?.strip_prefix(&format!("{}: ", tag))
+                .map(str::to_string)
+                .ok_or_else(|| SiweError::Format(format!("missing {}", tag)))
+        };
+        let uri = tagged("URI")?;
+        let version = tagged("Version")?;
+        if version != VERSION {
+            return Err(SiweError::Format(format!("unsupported version {}", version)));
+        }
+        let chain_id = tagged("Chain ID")?;
+        let chain_id = chain_id.parse()
+            .map_err(|_| SiweError::Format(format!("invalid chain ID {}", chain_id)))?;
+        let nonce = tagged("Nonce")?;
+        if nonce.len() < 8 || !nonce.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(SiweError::Format(format!("nonce {} must be at least 8 alphanumeric characters", nonce)));
+        }
+        let issued_at = parse_time("Issued At", &tagged("Issued At")?)?;
+
+        let mut optional = |tag: &str| -> Option<String> {
+            let value = lines.peek()?.strip_prefix(&format!("{}: ", tag))?.to_string();
+            lines.next();
+            Some(value)
+        };
+        let expiration_time = optional("Expiration Time")
+            .map(|value| parse_time("Expiration Time", &value))
+            .transpose()?;
+        let not_before = optional("Not Before")
+            .map(|value| parse_time("Not Before", &value))
+            .transpose()?;
+        let request_id = optional("Request ID");
+
+        let mut resources = Vec::new();
+        if lines.peek() == Some(&"Resources:") {
+            lines.next();
+            while let Some(resource) = lines.peek().and_then(|line| line.strip_prefix("- ")) {
+                resources.push(resource.to_string());
+                lines.next();
+            }
+        }
+        if let Some(line) = lines.next() {
+            return Err(SiweError::Format(format!("unexpected line {}", line)));
+        }
+
+        Ok(SiweMessage {
+            domain,
+            address,
+            statement,
+            uri,
+            chain_id,
+            nonce,
+            issued_at,
+            expiration_time,
+            not_before,
+            request_id,
+            resources,
+        })
+    }
+}
+
+/// Recover the signer of a hex encoded EIP-191 personal signature of `message`
+pub fn recover_signer(message: &str, signature: &str) -> Result<Address, SiweError> {
+    let sig_bytes = hex::decode(signature)
+        .map_err(|e| SiweError::Signature(format!("invalid hex: {}", e)))?;
+    if sig_bytes.len() != 65 {
+        return Err(SiweError::Signature("signature must be 65 bytes".to_string()));
+    }
+
+    let signature = Signature::try_from(sig_bytes.as_slice())
+        .map_err(|e| SiweError::Signature(e.to_string()))?;
+    signature.recover(hash_message(message))
+        .map_err(|e| SiweError::Signature(format!("recovery failed: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::{LocalWallet, Signer};
+    use time::Duration;
+
+    fn message(wallet: &LocalWallet) -> SiweMessage {
+        let issued_at = OffsetDateTime::from_unix_timestamp(1_760_000_000).unwrap();
+        SiweMessage {
+            domain: "shares.example.com".to_string(),
+            address: wallet.address(),
+            statement: Some("Join the Telegram group -1001234567890 as Telegram user 7346290155.".to_string()),
+            uri: "https://shares.example.com".to_string(),
+            chain_id: 10143,
+            nonce: "3f1c9a0b6d2e4f5a".to_string(),
+            issued_at,
+            expiration_time: Some(issued_at + Duration::minutes(5)),
+            not_before: None,
+            request_id: None,
+            resources: Vec::new(),
+        }
+    }
+
+    fn wallet() -> LocalWallet {
+        "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318".parse().unwrap()
+    }
+
+    #[test]
+    fn test_format_and_parse() {
+        let wallet = wallet();
+        let message = message(&wallet);
+        let text = message.to_string();
+        assert_eq!(
+            text,
+            format!(
+                "shares.example.com wants you to sign in with your Ethereum account:\n\
+                {}\n\
+                \n\
+                Join the Telegram group -1001234567890 as Telegram user 7346290155.\n\
+                \n\
+                URI: https://shares.example.com\n\
+                Version: 1\n\
+                Chain ID: 10143\n\
+                Nonce: 3f1c9a0b6d2e4f5a\n\
+                Issued At: 2025-10-09T08:53:20Z\n\
+                Expiration Time: 2025-10-09T08:58:20Z",
+                to_checksum(&wallet.address(), None)
+            )
+        );
+        assert_eq!(text.parse::<SiweMessage>().unwrap(), message);
+
+        let minimal = SiweMessage {
+            statement: None,
+            expiration_time: None,
+            request_id: Some("42".to_string()),
+            resources: vec!["ipfs://bafybeiemxf5abjwjbikoz4mc3a3dla6ual3jsgpdr4cjr3oz3evfyavhwq".to_string()],
+            ..message
+        };
+        assert_eq!(minimal.to_string().parse::<SiweMessage>().unwrap(), minimal);
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed() {
+        let text = message(&wallet()).to_string();
+        let checksummed = to_checksum(&wallet().address(), None);
+
+        assert!(text.replace(&checksummed, &checksummed.to_lowercase()).parse::<SiweMessage>().is_err());
+        assert!(text.replace("Version: 1", "Version: 2").parse::<SiweMessage>().is_err());
+        assert!(text.replace("Nonce: 3f1c9a0b6d2e4f5a", "Nonce: 3f1c").parse::<SiweMessage>().is_err());
+        assert!(text.replace("Issued At: 2025-10-09T08:53:20Z", "Issued At: yesterday").parse::<SiweMessage>().is_err());
+        assert!(format!("{}\nextra", text).parse::<SiweMessage>().is_err());
+        assert!("7346290155".parse::<SiweMessage>().is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let message = message(&wallet());
+        let now = message.issued_at + Duration::minutes(1);
+        let validate = |domain, uri, chain_id, now| message.validate(domain, uri, chain_id, now);
+
+        assert!(validate("shares.example.com", "https://shares.example.com", 10143, now).is_ok());
+        assert!(validate("phishing.example.com", "https://shares.example.com", 10143, now).is_err());
+        assert!(validate("shares.example.com", "https://phishing.example.com", 10143, now).is_err());
+        assert!(validate("shares.example.com", "https://shares.example.com", 1, now).is_err());
+        assert!(validate("shares.example.com", "https://shares.example.com", 10143, now + Duration::minutes(5)).is_err());
+        assert!(SiweMessage { not_before: Some(now + Duration::seconds(1)), ..message.clone() }
+            .validate("shares.example.com", "https://shares.example.com", 10143, now).is_err());
+    }
+
+    #[test]
+    fn test_recover_signer() {
+        let wallet = wallet();
+        let text = message(&wallet).to_string();
+        let signature = wallet.sign_hash(hash_message(&text)).unwrap();
+
+        assert_eq!(recover_signer(&text, &hex::encode(signature.to_vec())).unwrap(), wallet.address());
+        assert_ne!(recover_signer(&format!("{} ", text), &hex::encode(signature.to_vec())).unwrap(), wallet.address());
+        assert!(recover_signer(&text, "0x1234").is_err());
+    }
+}
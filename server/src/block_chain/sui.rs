@@ -1,32 +1,52 @@
+use std::collections::HashMap;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use anyhow::{Result, anyhow};
 use sqlx::types::BigDecimal;
 use sqlx::PgPool;
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use teloxide::Bot;
-use teloxide::prelude::{Requester, UserId};
-use teloxide::types::ChatPermissions;
 use async_trait::async_trait;
 use base64::prelude::*;
-use sui_sdk::types::crypto::{Signature, SignatureScheme};
-use sui_sdk::types::base_types::SuiAddress;
+use futures::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use tokio_tungstenite::tungstenite::Message;
+use fastcrypto_zkp::bn254::zk_login::{parse_jwks, OIDCProvider};
+use fastcrypto_zkp::bn254::zk_login_api::ZkLoginEnv;
+use im::hashmap::HashMap as ImHashMap;
+use shared_crypto::intent::{Intent, IntentMessage, PersonalMessage};
+use sui_sdk::types::crypto::ToFromBytes;
+use sui_sdk::types::signature::{AuthenticatorTrait, GenericSignature, VerifyParams};
+use sui_sdk::types::signature_verification::VerifiedDigestCache;
+use sui_sdk::types::base_types::{ObjectID, SequenceNumber, SuiAddress};
+use sui_sdk::types::programmable_transaction_builder::ProgrammableTransactionBuilder;
+use sui_sdk::types::transaction::{CallArg, ObjectArg, TransactionKind};
+use sui_sdk::types::Identifier;
 
 use crate::block_chain::Blockchain;
-use crate::db::operations::{get_last_synced_block, get_last_synced_block_with_metadata, process_buy_trade, process_sell_trade, update_last_synced_block, update_last_synced_block_with_metadata};
+use crate::block_chain::rpc::RpcTransport;
+use crate::block_chain::trade::{IndexedTrade, SyncCursor, commit_batch};
+use crate::db::models::{SuiCursor, TradeEventDetails, TradeEventKey};
+use crate::config::{SuiDeploymentConfig, ZkLoginJwkSource};
+use crate::db::operations::{assign_legacy_sui_cursor, get_last_synced_block, get_sui_cursor};
 use crate::AppConfig;
 
 /// Sui blockchain implementation
 pub struct SuiBlockchain {
-    rpc_url: String,
-    contract_address: String,
-    shares_trading_object_id: String,
+    rpc: RpcTransport,
+    ws_url: Option<String>,
+    deployments: Vec<SuiDeploymentConfig>,
+    sync_mode: SuiSyncMode,
     config: Arc<AppConfig>,
+    /// Initial shared versions of SharesTrading objects, they never change
+    shared_versions: Mutex<HashMap<ObjectID, SequenceNumber>>,
+    /// JWKs and rules zkLogin and multisig signatures are verified with
+    verify_params: VerifyParams,
 }
 
+/// `parsedJson` of a Trade event, only used when its BCS cannot be decoded
 #[derive(Debug, Serialize, Deserialize)]
 struct SuiTradeEvent {
     /// Trader address
@@ -47,6 +67,50 @@ struct SuiTradeEvent {
     supply: String,
 }
 
+/// Move `shares_trading::Trade` event, fields in declaration order as BCS encodes them
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct MoveTrade {
+    trader: SuiAddress,
+    subject: SuiAddress,
+    is_buy: bool,
+    amount: u64,
+    price: u64,
+    protocol_fee: u64,
+    subject_fee: u64,
+    supply: u64,
+}
+
+impl MoveTrade {
+    /// Decode the BCS bytes of an event, base64 or base58 encoded depending on the node version
+    fn from_bcs(bcs_data: &str, encoding: &str) -> Result<Self> {
+        let bytes = match encoding {
+            "base64" => BASE64_STANDARD.decode(bcs_data)?,
+            "base58" | "" => bs58::decode(bcs_data).into_vec()?,
+            _ => return Err(anyhow!("Unknown BCS encoding {}", encoding)),
+        };
+        Ok(bcs::from_bytes(&bytes)?)
+    }
+}
+
+impl TryFrom<&SuiTradeEvent> for MoveTrade {
+    type Error = anyhow::Error;
+
+    fn try_from(trade: &SuiTradeEvent) -> Result<Self> {
+        let parse_u64 = |field: &str, value: &str| value.parse::<u64>()
+            .map_err(|e| anyhow!("Cannot parse {} {}: {:?}", field, value, e));
+        Ok(MoveTrade {
+            trader: SuiAddress::from_str(&trade.trader)?,
+            subject: SuiAddress::from_str(&trade.subject)?,
+            is_buy: trade.is_buy,
+            amount: parse_u64("amount", &trade.amount)?,
+            price: parse_u64("price", &trade.price)?,
+            protocol_fee: parse_u64("protocol_fee", &trade.protocol_fee)?,
+            subject_fee: parse_u64("subject_fee", &trade.subject_fee)?,
+            supply: parse_u64("supply", &trade.supply)?,
+        })
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct SuiEventPage {
     data: Vec<SuiEvent>,
@@ -55,7 +119,7 @@ struct SuiEventPage {
 }
 
 /// Sui event cursor structure
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 struct EventID {
     /// Transaction digest
     #[serde(rename = "txDigest")]
@@ -68,8 +132,9 @@ struct EventID {
 #[derive(Debug, Serialize, Deserialize)]
 struct SuiEvent {
     id: EventID,
-    #[serde(rename = "timestampMs")]
-    timestamp_ms: String,
+    /// Missing on events embedded in transaction blocks
+    #[serde(rename = "timestampMs", default)]
+    timestamp_ms: Option<String>,
     #[serde(rename = "transactionModule")]
     transaction_module: String,
     #[serde(rename = "type")]
@@ -77,27 +142,134 @@ struct SuiEvent {
     sender: String,
     #[serde(rename = "packageId")]
     package_id: String,
-    #[serde(rename = "parsedJson")]
-    parsed_json: SuiTradeEvent,
+    #[serde(rename = "parsedJson", default)]
+    parsed_json: Value,
+    #[serde(default)]
     bcs: String,
-    #[serde(rename = "bcsEncoding")]
+    /// Missing on nodes that always return base58
+    #[serde(rename = "bcsEncoding", default)]
     bcs_encoding: String,
+    /// Not part of the event, looked up from its transaction
+    #[serde(skip)]
+    checkpoint: Option<i64>,
+}
+
+/// Result of `sui_devInspectTransactionBlock`
+#[derive(Debug, Deserialize)]
+struct DevInspectResults {
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    results: Vec<DevInspectCallResult>,
+}
+
+/// Outcome of one command of an inspected transaction
+#[derive(Debug, Deserialize)]
+struct DevInspectCallResult {
+    /// BCS bytes and Move type of each returned value
+    #[serde(rename = "returnValues", default)]
+    return_values: Vec<(Vec<u8>, String)>,
+}
+
+/// Number of events requested per `suix_queryEvents` page
+const EVENT_PAGE_LIMIT: u64 = 100;
+/// Number of checkpoints committed per batch in checkpoint mode
+const CHECKPOINT_BATCH_SIZE: u64 = 20;
+/// Maximum digests accepted by `sui_multiGetTransactionBlocks`
+const MULTI_GET_TX_LIMIT: usize = 50;
+/// Interval between catch-up pages while the event subscription is live
+const STREAMING_POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// Delay before reconnecting a dropped event subscription
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Epochs a zkLogin ephemeral key may be valid for ahead of the current epoch, as on mainnet
+const ZKLOGIN_MAX_EPOCH_UPPER_BOUND_DELTA: u64 = 30;
+/// Back-off bounds when the node keeps returning the same cursor
+const MIN_STALL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_STALL_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How Sui Trade events are discovered
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SuiSyncMode {
+    /// Paginate `suix_queryEvents` filtered by the Trade event type
+    Events,
+    /// Walk checkpoints sequentially and filter their transaction events
+    Checkpoints,
+}
+
+impl FromStr for SuiSyncMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "events" => Ok(SuiSyncMode::Events),
+            "checkpoints" => Ok(SuiSyncMode::Checkpoints),
+            _ => Err(format!("Unknown Sui sync mode: {}", s)),
+        }
+    }
+}
+
+type SuiEventStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Pagination state after fetching an events page
+#[derive(Debug, PartialEq)]
+enum PageState {
+    /// More pages are available right away
+    Fetching,
+    /// Reached the newest event, poll again later
+    CaughtUp,
+    /// Node reports more pages but did not move the cursor
+    Stalled,
+}
+
+impl PageState {
+    fn of(cursor: Option<&EventID>, page: &SuiEventPage) -> Self {
+        if !page.hasNextPage {
+            return PageState::CaughtUp;
+        }
+        match &page.nextCursor {
+            Some(next) if Some(next) != cursor => PageState::Fetching,
+            _ => PageState::Stalled,
+        }
+    }
 }
 
 impl SuiBlockchain {
     pub fn new(config: Arc<AppConfig>) -> Self {
-        let rpc_url = config.sui_rpc.clone().unwrap_or_else(|| "https://fullnode.mainnet.sui.io:443".to_string());
-        let contract_address = config.sui_contract.clone().unwrap_or_else(|| "0x000".to_string());
-        let shares_trading_object_id = config.sui_shares_trading_object_id.clone().unwrap_or_else(|| "0x000".to_string());
+        let rpc_urls = if config.sui_rpc.is_empty() {
+            vec!["https://fullnode.mainnet.sui.io:443".to_string()]
+        } else {
+            config.sui_rpc.clone()
+        };
+        let rpc = RpcTransport::new("sui", &rpc_urls, config.rpc_requests_per_second)
+            .expect("Failed to create Sui RPC client");
+        let verify_params = zklogin_verify_params(&config.sui_zklogin_jwks, config.sui_zklogin_test_env)
+            .expect("Failed to load zkLogin JWKs");
         
         Self {
-            rpc_url,
-            contract_address,
-            shares_trading_object_id,
+            rpc,
+            ws_url: config.sui_ws_rpc.clone(),
+            deployments: config.sui_deployments.clone(),
+            sync_mode: config.sui_sync_mode,
             config,
+            shared_versions: Mutex::new(HashMap::new()),
+            verify_params,
         }
     }
     
+    /// Trade event types of every package version of every deployment
+    fn trade_event_types(&self) -> Vec<String> {
+        self.deployments.iter()
+            .flat_map(|deployment| &deployment.package_ids)
+            .map(|package_id| trade_event_type(package_id))
+            .collect()
+    }
+    
+    /// Deployment whose packages emit events of `event_type`
+    fn deployment_of(&self, event_type: &str) -> Option<&SuiDeploymentConfig> {
+        let package_id = event_type.split("::").next()?.to_lowercase();
+        self.deployments.iter().find(|deployment| deployment.package_ids.contains(&package_id))
+    }
+    
     /// Remove 0x prefix from address
     fn remove_0x_prefix(&self, address: &str) -> String {
         if address.starts_with("0x") {
@@ -107,360 +279,781 @@ impl SuiBlockchain {
         }
     }
     
-    /// Process Sui trade event
-    async fn process_trade_event(&self, event: &SuiTradeEvent, pool: &sqlx::PgPool) -> Result<()> {
-        println!("Processing Sui Trade event: {:?}", event);
-        
-        // Parse string to u64
-        let share_amount = match event.amount.parse::<u64>() {
-            Ok(amount) => BigDecimal::from(amount),
+    /// Decode a Trade event from its BCS bytes, falling back to `parsedJson`
+    fn decode_trade(event: &SuiEvent) -> Result<MoveTrade> {
+        match MoveTrade::from_bcs(&event.bcs, &event.bcs_encoding) {
+            Ok(trade) => Ok(trade),
             Err(e) => {
-                println!("Cannot parse transaction amount: {} - {:?}", event.amount, e);
-                return Err(anyhow!("Cannot parse transaction amount"));
+                println!("Cannot decode BCS of Sui event {:?}, using parsedJson: {:?}", event.id, e);
+                let trade: SuiTradeEvent = serde_json::from_value(event.parsed_json.clone())?;
+                MoveTrade::try_from(&trade)
             }
-        };
+        }
+    }
+    
+    /// Normalize a Sui Trade event into a chain-agnostic trade
+    fn to_indexed_trade(&self, event: &SuiEvent) -> Result<IndexedTrade> {
+        // Only decode events whose type is the Trade struct of a configured package
+        let deployment = self.deployment_of(&event.event_type)
+            .filter(|deployment| deployment.package_ids.iter()
+                .any(|package_id| trade_event_type(package_id) == event.event_type))
+            .ok_or_else(|| anyhow!("No deployment configured for event type {}", event.event_type))?;
+        let trade = Self::decode_trade(event)?;
         
-        // Remove 0x prefix from address
-        let trader = self.remove_0x_prefix(&event.trader);
-        let subject = self.remove_0x_prefix(&event.subject);
-        
-        if event.is_buy {
-            // Buy operation, increase shares
-            process_buy_trade(
-                pool, 
-                trader.clone(),
-                subject.clone(),
-                share_amount,
-                self.get_name(),
-            ).await?;
-            
-            // Check if user is banned
-            let user_mapping = sqlx::query!(
-                "SELECT telegram_id, is_banned FROM user_mappings WHERE address = $1 AND chain_type = $2",
-                trader.clone(), 
-                self.get_name()
-            )
-            .fetch_optional(pool)
-            .await?;
-            
-            if let Some(user) = user_mapping {
-                if user.is_banned {
-                    let user_share = sqlx::query!(
-                        "SELECT share_amount FROM trades WHERE trader = $1 AND subject = $2 AND chain_type = $3",
-                        trader.clone(),
-                        subject.clone(),
-                        self.get_name()
-                    )
-                    .fetch_optional(pool)
-                    .await?;
-                    
-                    if let Some(share) = user_share {
-                        if share.share_amount > BigDecimal::from(0) {
-                            let bot_info = sqlx::query!(
-                                "SELECT bot_token, chat_group_id FROM telegram_bots WHERE subject_address = $1 AND chain_type = $2",
-                                subject.clone(),
-                                self.get_name()
-                            )
-                            .fetch_optional(pool)
-                            .await?;
-                            
-                            if let Some(bot_info) = bot_info {
-                                let permissions = ChatPermissions::empty()
-                                    | ChatPermissions::SEND_MESSAGES
-                                    | ChatPermissions::SEND_MEDIA_MESSAGES
-                                    | ChatPermissions::SEND_OTHER_MESSAGES
-                                    | ChatPermissions::SEND_POLLS
-                                    | ChatPermissions::ADD_WEB_PAGE_PREVIEWS;
-
-                                let bot = Bot::new(bot_info.bot_token);
-                                let user_id: u64 = user.telegram_id.parse().unwrap();
-                                bot.restrict_chat_member(bot_info.chat_group_id, UserId(user_id), permissions).await?;
-                            }
-                        }
-                    }
+        Ok(IndexedTrade {
+            key: TradeEventKey {
+                tx_hash: event.id.tx_digest.clone(),
+                log_index: event.id.event_seq.parse::<i64>()
+                    .map_err(|e| anyhow!("Cannot parse eventSeq {}: {:?}", event.id.event_seq, e))?,
+                block_number: None,
+            },
+            // Remove 0x prefix from address
+            trader: self.remove_0x_prefix(&trade.trader.to_string()),
+            subject: self.remove_0x_prefix(&trade.subject.to_string()),
+            is_buy: trade.is_buy,
+            share_amount: BigDecimal::from(trade.amount),
+            deployment: deployment.object_id.clone(),
+            details: TradeEventDetails {
+                price: BigDecimal::from(trade.price),
+                protocol_fee: BigDecimal::from(trade.protocol_fee),
+                subject_fee: BigDecimal::from(trade.subject_fee),
+                supply: BigDecimal::from(trade.supply),
+                timestamp_ms: event.timestamp_ms.as_deref()
+                    .and_then(|timestamp_ms| timestamp_ms.parse::<i64>().ok()),
+                block_hash: None,
+                checkpoint: event.checkpoint,
+                raw: serde_json::to_value(event)?,
+            },
+        })
+    }
+    
+    /// Normalize the Trade events of a page or checkpoint range. Events that cannot be decoded
+    /// are logged and skipped: decoding them again fails the same way and would stop the cursor.
+    fn to_indexed_trades(&self, events: &[SuiEvent]) -> Vec<IndexedTrade> {
+        events.iter()
+            .filter_map(|event| match self.to_indexed_trade(event) {
+                Ok(trade) => Some(trade),
+                Err(e) => {
+                    println!(
+                        "Skipping undecodable {} event {}:{} of type {}: {:#}",
+                        self.get_name(), event.id.tx_digest, event.id.event_seq, event.event_type, e
+                    );
+                    None
                 }
-            }
-        } else {
-            // Sell operation, decrease shares
-            println!("Trader {} sell {} shares of subject {}", trader, share_amount, subject);
-            let (should_ban, telegram_id_opt) = process_sell_trade(
-                pool,
-                trader.clone(),
-                subject.clone(),
-                share_amount,
-                self.get_name(),
-            ).await?;
+            })
+            .collect()
+    }
+    
+    /// Build the persisted cursor for the last event of a page
+    fn to_sui_cursor(event_type: &str, next: &EventID, page: &SuiEventPage) -> Result<SuiCursor> {
+        let event_seq = next.event_seq.parse::<i64>()
+            .map_err(|e| anyhow!("Cannot parse eventSeq {}: {:?}", next.event_seq, e))?;
+        let last_event = page.data.iter().find(|event| &event.id == next);
+        let timestamp_ms = last_event
+            .and_then(|event| event.timestamp_ms.as_deref())
+            .and_then(|timestamp_ms| timestamp_ms.parse::<i64>().ok());
+        
+        Ok(SuiCursor {
+            event_type: event_type.to_string(),
+            tx_digest: next.tx_digest.clone(),
+            event_seq,
+            checkpoint: last_event.and_then(|event| event.checkpoint),
+            timestamp_ms,
+        })
+    }
+    
+    /// Fill in the checkpoint of events returned without one, looked up from their transactions.
+    /// The checkpoint is informational, a failed lookup must not block syncing
+    async fn fill_event_checkpoints(&self, events: &mut [SuiEvent]) {
+        let mut digests: Vec<String> = events.iter()
+            .filter(|event| event.checkpoint.is_none())
+            .map(|event| event.id.tx_digest.clone())
+            .collect();
+        digests.sort();
+        digests.dedup();
+        
+        let mut checkpoints: HashMap<String, i64> = HashMap::new();
+        for chunk in digests.chunks(MULTI_GET_TX_LIMIT) {
+            let transactions = match self.rpc_call("sui_multiGetTransactionBlocks", json!([chunk, {}])).await {
+                Ok(transactions) => transactions,
+                Err(e) => {
+                    println!("Failed to get checkpoints of {} transactions: {:?}", chunk.len(), e);
+                    continue;
+                }
+            };
             
-            if should_ban {
-                if let Some(telegram_id) = telegram_id_opt {
-                    println!("User {} has 0 shares for {}, banning user", &trader, &subject);
-                    
-                    // Get the bot token and chat group id from telegram_bots table for this subject
-                    let bot_info = sqlx::query!(
-                        "SELECT bot_token, chat_group_id FROM telegram_bots WHERE subject_address = $1 AND chain_type = $2",
-                        subject.clone(),
-                        self.get_name()
-                    )
-                    .fetch_optional(pool)
-                    .await?;
-                    
-                    if let Some(bot_info) = bot_info {
-                        let permissions = ChatPermissions::empty();
-
-                        let bot = Bot::new(bot_info.bot_token);
-                        let user_id: u64 = telegram_id.parse().unwrap();
-                        bot.restrict_chat_member(bot_info.chat_group_id, UserId(user_id), permissions).await?;
-                        sqlx::query!(
-                            "UPDATE user_mappings SET is_banned = true WHERE address = $1 AND chain_type = $2",
-                            trader.clone(),
-                            self.get_name()
-                        )
-                        .execute(pool)
-                        .await?;
-                    } else {
-                        println!("No telegram bot info found for subject {}", &subject);
-                    }
+            for transaction in transactions.as_array().into_iter().flatten() {
+                let digest = transaction.get("digest").and_then(|digest| digest.as_str());
+                let checkpoint = transaction.get("checkpoint")
+                    .and_then(|checkpoint| checkpoint.as_str())
+                    .and_then(|checkpoint| checkpoint.parse::<i64>().ok());
+                if let (Some(digest), Some(checkpoint)) = (digest, checkpoint) {
+                    checkpoints.insert(digest.to_string(), checkpoint);
                 }
             }
         }
-        Ok(())
+        
+        for event in events.iter_mut().filter(|event| event.checkpoint.is_none()) {
+            event.checkpoint = checkpoints.get(&event.id.tx_digest).copied();
+        }
     }
     
-    /// Call Sui RPC to get events
-    async fn get_events(&self, start_cursor: Option<String>, limit: u64) -> Result<SuiEventPage> {
-        let client = Client::new();
-        
+    /// Call Sui RPC to get events of `event_type` after `cursor`
+    async fn get_events(&self, event_type: &str, cursor: Option<&EventID>, limit: u64) -> Result<SuiEventPage> {
         // Build query JSON
-        let query_type = if self.contract_address.is_empty() {
-            // Use MoveEvent event type
-            json!({
-                "MoveEventType": "package::module::Trade"
-            })
-        } else {
-            // Use specific package address
-            json!({
-                "MoveEventType": format!("{}::shares_trading::Trade", self.contract_address)
-            })
-        };
+        let query_type = json!({
+            "MoveEventType": event_type
+        });
         
-        // Process cursor parameter
-        let cursor_param: Option<serde_json::Value> = match start_cursor {
-            Some(cursor_str) => {
-                // Check if already JSON format
-                if cursor_str.trim().starts_with('{') {
-                    match serde_json::from_str(&cursor_str) {
-                        Ok(json_val) => Some(json_val),
-                        Err(_) => {
-                            // If parsing fails, try to create a new EventID
-                            // Use valid transaction hash (64 hexadecimal characters)
-                            Some(json!({
-                                "txDigest": "0000000000000000000000000000000000000000000000000000000000000000",
-                                "eventSeq": cursor_str
-                            }))
-                        }
-                    }
-                } else {
-                    // Assume simple string, wrap as EventID structure
-                    // Use valid transaction hash (64 hexadecimal characters)
-                    Some(json!({
-                        "txDigest": "0000000000000000000000000000000000000000000000000000000000000000",
-                        "eventSeq": cursor_str
-                    }))
-                }
-            },
-            None => None,
-        };
+        let cursor_param = cursor.map(|cursor| json!(cursor));
         
-        let payload = json!({
-            "jsonrpc": "2.0",
-            "id": 1,
-            "method": "suix_queryEvents",
-            "params": {
-                "query": query_type,
-                "cursor": cursor_param,
-                "limit": limit,
-                "descending_order": false
+        let params = json!({
+            "query": query_type,
+            "cursor": cursor_param,
+            "limit": limit,
+            "descending_order": false
+        });
+        
+        let result = self.rpc_call("suix_queryEvents", params).await?;
+        let events: SuiEventPage = serde_json::from_value(result)?;
+        Ok(events)
+    }
+    
+    /// Sync every Trade event type, each paginated with its own cursor
+    async fn sync_event_types(&self, pool: &PgPool) -> Result<()> {
+        let event_types = self.trade_event_types();
+        // The cursor saved before cursors were kept per event type belongs to the first package
+        if let Some(event_type) = event_types.first() {
+            assign_legacy_sui_cursor(pool, self.get_name(), event_type).await?;
+        }
+        
+        let syncs = event_types.iter().map(|event_type| async move {
+            let result = match &self.ws_url {
+                Some(ws_url) => self.stream_events(pool, ws_url, event_type).await,
+                None => self.sync_query_events(pool, event_type).await,
+            };
+            if let Err(e) = result {
+                println!("Error syncing Sui events of {}: {:?}", event_type, e);
             }
         });
+        futures::future::join_all(syncs).await;
+        Ok(())
+    }
+    
+    /// Sync by paginating `suix_queryEvents` from the persisted event cursor
+    async fn sync_query_events(&self, pool: &PgPool, event_type: &str) -> Result<()> {
+        let mut cursor = self.load_event_cursor(pool, event_type).await?;
+        
+        println!("Starting sync of {} from cursor {:?} for {}", event_type, cursor, self.get_name());
         
-        let response = client.post(&self.rpc_url)
-            .json(&payload)
-            .send()
-            .await?;
+        let mut stall_backoff = MIN_STALL_BACKOFF;
         
-        if !response.status().is_success() {
-            return Err(anyhow!("Sui RPC request failed: {}", response.status()));
+        // Event sync loop
+        loop {
+            match self.sync_next_page(pool, event_type, &mut cursor).await {
+                Ok(PageState::Fetching) => {
+                    stall_backoff = MIN_STALL_BACKOFF;
+                    // Brief rest, avoid too frequent requests
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                },
+                Ok(PageState::CaughtUp) => {
+                    stall_backoff = MIN_STALL_BACKOFF;
+                    // No more events, wait for new events
+                    println!("No more events available for {}, waiting for new events...", self.get_name());
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                },
+                Ok(PageState::Stalled) => {
+                    println!("Sui node reported more events but returned cursor {:?} again, backing off {:?}", cursor, stall_backoff);
+                    tokio::time::sleep(stall_backoff).await;
+                    stall_backoff = std::cmp::min(stall_backoff * 2, MAX_STALL_BACKOFF);
+                },
+                Err(e) => {
+                    println!("Failed to sync Sui events page, will retry: {:?}", e);
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                }
+            }
         }
+    }
+    
+    /// Restore the persisted event cursor, `None` starts from the first event
+    async fn load_event_cursor(&self, pool: &PgPool, event_type: &str) -> Result<Option<EventID>> {
+        Ok(get_sui_cursor(pool, self.get_name(), event_type).await?
+            .map(|saved| EventID {
+                tx_digest: saved.tx_digest,
+                event_seq: saved.event_seq.to_string(),
+            }))
+    }
+    
+    /// Fetch the page after `cursor` and commit its events together with the new cursor
+    async fn sync_next_page(&self, pool: &PgPool, event_type: &str, cursor: &mut Option<EventID>) -> Result<PageState> {
+        let mut page = self.get_events(event_type, cursor.as_ref(), EVENT_PAGE_LIMIT).await?;
+        let state = PageState::of(cursor.as_ref(), &page);
+        self.fill_event_checkpoints(&mut page.data).await;
         
-        let response_json: Value = response.json().await?;
+        let trades = self.to_indexed_trades(&page.data);
         
-        if let Some(error) = response_json.get("error") {
-            return Err(anyhow!("Sui RPC returned error: {}", error));
-        }
+        // Only persist a cursor that actually moved
+        let next_cursor = page.nextCursor.clone().filter(|next| Some(next) != cursor.as_ref());
+        let sync_cursor = match &next_cursor {
+            Some(next) => Some(SyncCursor::SuiEvent(Self::to_sui_cursor(event_type, next, &page)?)),
+            None => None,
+        };
         
-        // Parse result
-        if let Some(result) = response_json.get("result") {
-            // println!("result: {:?}", result);
-            let events: SuiEventPage = serde_json::from_value(result.clone())?;
-            return Ok(events);
-        }
+        // Apply all events and advance the cursor atomically, the whole page is retried on failure
+        commit_batch(pool, self.get_name(), &trades, sync_cursor).await?;
         
-        Err(anyhow!("Cannot parse Sui RPC response"))
+        if let Some(next) = next_cursor {
+            *cursor = Some(next);
+        }
+        Ok(state)
+    }
+    
+    /// Page through events until caught up with the node
+    async fn catch_up_events(&self, pool: &PgPool, event_type: &str, cursor: &mut Option<EventID>) -> Result<()> {
+        while self.sync_next_page(pool, event_type, cursor).await? == PageState::Fetching {}
+        Ok(())
     }
     
-    /// Get shares on Sui
-    async fn get_sui_shares(&self, subject: &str, user: &str) -> Result<u64> {
-        let client = Client::new();
+    /// Stream Trade events via `suix_subscribeEvent`. After every (re)connect, and periodically
+    /// while streaming, events are paged from the persisted cursor so no gap is left behind.
+    async fn stream_events(&self, pool: &PgPool, ws_url: &str, event_type: &str) -> Result<()> {
+        let mut cursor = self.load_event_cursor(pool, event_type).await?;
         
-        // Remove address prefix, ensure consistency
-        let clean_subject = self.remove_0x_prefix(subject);
-        let clean_user = self.remove_0x_prefix(user);
+        println!("Starting streaming sync of {} from cursor {:?} for {}", event_type, cursor, self.get_name());
         
-        // For RPC call, need to add back 0x prefix
-        let subject_with_prefix = format!("0x{}", clean_subject);
-        let user_with_prefix = format!("0x{}", clean_user);
+        loop {
+            match self.subscribe_trade_events(ws_url, event_type).await {
+                Ok(mut ws) => {
+                    println!("Subscribed to Trade events for {}", self.get_name());
+                    
+                    // The first tick fires immediately: catch up once subscribed, so nothing emitted meanwhile is lost
+                    let mut catch_up = tokio::time::interval(STREAMING_POLL_INTERVAL);
+                    loop {
+                        tokio::select! {
+                            message = ws.next() => match message {
+                                Some(Ok(Message::Text(text))) => {
+                                    if let Err(e) = self.apply_streamed_event(pool, event_type, &text).await {
+                                        println!("Error processing streamed Sui event: {:?}", e);
+                                    }
+                                },
+                                Some(Ok(Message::Close(_))) | None => break,
+                                Some(Ok(_)) => {},
+                                Some(Err(e)) => {
+                                    println!("Sui event subscription error: {:?}", e);
+                                    break;
+                                },
+                            },
+                            _ = catch_up.tick() => {
+                                if let Err(e) = self.catch_up_events(pool, event_type, &mut cursor).await {
+                                    println!("Failed to catch up Sui events: {:?}", e);
+                                }
+                            },
+                        }
+                    }
+                    println!("Sui event subscription closed for {}", self.get_name());
+                },
+                Err(e) => println!("Failed to subscribe to Sui events: {:?}", e),
+            }
+            
+            // Fill the gap from the persisted cursor before resuming streaming
+            if let Err(e) = self.catch_up_events(pool, event_type, &mut cursor).await {
+                println!("Failed to catch up Sui events: {:?}", e);
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+    
+    /// Open a WebSocket connection subscribed to a Trade event type
+    async fn subscribe_trade_events(&self, ws_url: &str, event_type: &str) -> Result<SuiEventStream> {
+        let (mut ws, _) = connect_async(ws_url).await?;
         
-        // Build JSON-RPC request to call smart contract function
-        let payload = json!({
+        let request = json!({
             "jsonrpc": "2.0",
-            "method": "sui_devInspectTransactionBlock",
-            "params": [
-                "0x0", // Sender address (meaningless, just reading state)
-                {
-                    "kind": "moveCall",
-                    "data": {
-                        "packageObjectId": self.contract_address,
-                        "module": "shares_trading",
-                        "function": "get_shares_balance",
-                        "arguments": [
-                            self.shares_trading_object_id,
-                            subject_with_prefix,
-                            user_with_prefix
-                        ]
-                    }
-                }
-            ],
-            "id": 1
+            "id": 1,
+            "method": "suix_subscribeEvent",
+            "params": [{ "MoveEventType": event_type }]
         });
+        ws.send(Message::Text(request.to_string().into())).await?;
         
-        let response = client.post(&self.rpc_url)
-            .json(&payload)
-            .send()
-            .await?;
+        Ok(ws)
+    }
+    
+    /// Apply an event notification received on the subscription, without moving the cursor
+    async fn apply_streamed_event(&self, pool: &PgPool, event_type: &str, message: &str) -> Result<()> {
+        let mut message: Value = serde_json::from_str(message)?;
         
-        if !response.status().is_success() {
-            return Err(anyhow!("Sui RPC request failed: {}", response.status()));
+        if let Some(error) = message.get("error") {
+            return Err(anyhow!("Sui subscription returned error: {}", error));
         }
         
-        let response_json: Value = response.json().await?;
+        // Other messages are the subscription acknowledgement
+        let Some(event) = message.pointer_mut("/params/result").map(Value::take) else {
+            return Ok(());
+        };
         
-        if let Some(error) = response_json.get("error") {
-            return Err(anyhow!("Sui RPC returned error: {}", error));
+        let mut event: SuiEvent = serde_json::from_value(event)?;
+        if event.event_type != event_type {
+            return Ok(());
         }
+        self.fill_event_checkpoints(std::slice::from_mut(&mut event)).await;
         
-        // Parse return result (actual deployment needs to adjust based on contract's specific return format)
-        if let Some(result) = response_json.get("result").and_then(|r| r.get("results")).and_then(|r| r.as_array()) {
-            if let Some(first_result) = result.first() {
-                if let Some(return_values) = first_result.get("returnValues").and_then(|v| v.as_array()) {
-                    if let Some(first_value) = return_values.first() {
-                        if let Some(balance) = first_value.as_u64() {
-                            return Ok(balance);
-                        }
+        let trade = self.to_indexed_trade(&event)?;
+        commit_batch(pool, self.get_name(), &[trade], None).await?;
+        Ok(())
+    }
+    
+    /// Sync by walking checkpoints sequentially, checkpoints are final so no event can be skipped
+    async fn sync_checkpoints(&self, pool: &PgPool) -> Result<()> {
+        // Start right before the configured checkpoint, or at the current one when none is configured
+        let start_checkpoint = match self.config.sui_start_checkpoint {
+            Some(checkpoint) => checkpoint.saturating_sub(1),
+            None => self.get_latest_checkpoint().await?,
+        };
+        let mut last_checkpoint = get_last_synced_block(pool, start_checkpoint, self.get_name()).await?;
+        
+        println!("Starting sync from checkpoint {} for {}", last_checkpoint, self.get_name());
+        
+        loop {
+            let latest_checkpoint = match self.get_latest_checkpoint().await {
+                Ok(checkpoint) => checkpoint,
+                Err(e) => {
+                    println!("Failed to get latest Sui checkpoint: {:?}", e);
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    continue;
+                }
+            };
+            
+            if last_checkpoint >= latest_checkpoint {
+                tokio::time::sleep(Duration::from_secs(2)).await;
+                continue;
+            }
+            
+            let end_checkpoint = std::cmp::min(last_checkpoint + CHECKPOINT_BATCH_SIZE, latest_checkpoint);
+            
+            let mut events = Vec::new();
+            let mut fetched = true;
+            for checkpoint in last_checkpoint + 1..=end_checkpoint {
+                match self.get_checkpoint_trade_events(checkpoint).await {
+                    Ok(checkpoint_events) => events.extend(checkpoint_events),
+                    Err(e) => {
+                        println!("Failed to get events of checkpoint {}: {:?}", checkpoint, e);
+                        fetched = false;
+                        break;
+                    }
+                }
+            }
+            if !fetched {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                continue;
+            }
+            
+            let trades = self.to_indexed_trades(&events);
+            
+            // Apply all events and advance the checkpoint atomically, retry the whole range on failure
+            match commit_batch(pool, self.get_name(), &trades, Some(SyncCursor::Block(end_checkpoint))).await {
+                Ok(()) => {
+                    last_checkpoint = end_checkpoint;
+                },
+                Err(e) => {
+                    println!("Failed to commit checkpoints {} to {}, will retry: {:?}", last_checkpoint + 1, end_checkpoint, e);
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                }
+            }
+        }
+    }
+    
+    /// Get the sequence number of the latest executed checkpoint
+    async fn get_latest_checkpoint(&self) -> Result<u64> {
+        let result = self.rpc_call("sui_getLatestCheckpointSequenceNumber", json!([])).await?;
+        result.as_str()
+            .and_then(|checkpoint| checkpoint.parse::<u64>().ok())
+            .ok_or_else(|| anyhow!("Invalid checkpoint sequence number: {}", result))
+    }
+    
+    /// Get the Trade events emitted by the transactions of a checkpoint
+    async fn get_checkpoint_trade_events(&self, checkpoint: u64) -> Result<Vec<SuiEvent>> {
+        let result = self.rpc_call("sui_getCheckpoint", json!([checkpoint.to_string()])).await?;
+        let digests: Vec<String> = serde_json::from_value(result.get("transactions").cloned().unwrap_or_default())?;
+        
+        let trade_event_types = self.trade_event_types();
+        let mut events = Vec::new();
+        
+        for chunk in digests.chunks(MULTI_GET_TX_LIMIT) {
+            let transactions = self.rpc_call("sui_multiGetTransactionBlocks", json!([chunk, { "showEvents": true }])).await?;
+            
+            for transaction in transactions.as_array().into_iter().flatten() {
+                let timestamp_ms = transaction.get("timestampMs").cloned();
+                
+                for event in transaction.get("events").and_then(|events| events.as_array()).into_iter().flatten() {
+                    let event_type = event.get("type").and_then(|event_type| event_type.as_str()).unwrap_or_default();
+                    if !trade_event_types.iter().any(|trade_event_type| trade_event_type == event_type) {
+                        continue;
                     }
+                    
+                    let mut event: SuiEvent = serde_json::from_value(event.clone())?;
+                    if event.timestamp_ms.is_none() {
+                        event.timestamp_ms = timestamp_ms.as_ref().and_then(|ts| ts.as_str()).map(str::to_string);
+                    }
+                    event.checkpoint = Some(checkpoint as i64);
+                    events.push(event);
                 }
             }
         }
         
-        // Default return 0
-        Ok(0)
+        Ok(events)
+    }
+    
+    /// Send a JSON-RPC request to the Sui node and return its result
+    async fn rpc_call(&self, method: &str, params: Value) -> Result<Value> {
+        Ok(self.rpc.request_value(method, params).await?)
+    }
+    
+    /// Epoch the network is in, zkLogin signatures expire after their max epoch
+    async fn current_epoch(&self) -> Result<u64> {
+        let state = self.rpc_call("suix_getLatestSuiSystemState", json!([])).await?;
+        state.get("epoch")
+            .and_then(|epoch| epoch.as_str())
+            .and_then(|epoch| epoch.parse().ok())
+            .ok_or_else(|| anyhow!("No epoch in system state"))
+    }
+    
+    /// Initial shared version of a SharesTrading object, needed to pass it to a transaction
+    async fn shared_version(&self, object_id: ObjectID) -> Result<SequenceNumber> {
+        if let Some(version) = self.shared_versions.lock().unwrap().get(&object_id) {
+            return Ok(*version);
+        }
+        
+        let object = self.rpc_call("sui_getObject", json!([object_id.to_string(), { "showOwner": true }])).await?;
+        let version = object.pointer("/data/owner/Shared/initial_shared_version")
+            .and_then(|version| version.as_u64().or_else(|| version.as_str()?.parse().ok()))
+            .ok_or_else(|| anyhow!("Object {} is not shared: {}", object_id, object))?;
+        let version = SequenceNumber::from_u64(version);
+        self.shared_versions.lock().unwrap().insert(object_id, version);
+        Ok(version)
+    }
+    
+    /// Call a read-only `shares_trading` function taking the SharesTrading object and addresses,
+    /// e.g. `get_shares_balance` or `get_current_supply`, and decode its u64 result
+    async fn call_u64_view(&self, deployment: &str, function: &str, addresses: &[&str]) -> Result<u64> {
+        let deployment = self.deployments.iter()
+            .find(|candidate| candidate.object_id == deployment)
+            .ok_or_else(|| anyhow!("Deployment {} is not indexed on {}", deployment, self.get_name()))?;
+        // Functions are called through the latest package version
+        let package_id = deployment.package_ids.last()
+            .ok_or_else(|| anyhow!("Deployment {} has no package", deployment.object_id))?;
+        let package_id = ObjectID::from_hex_literal(package_id)?;
+        let object_id = ObjectID::from_hex_literal(&deployment.object_id)?;
+        
+        let mut call_args = vec![CallArg::Object(ObjectArg::SharedObject {
+            id: object_id,
+            initial_shared_version: self.shared_version(object_id).await?,
+            mutable: false,
+        })];
+        for address in addresses {
+            let address = SuiAddress::from_str(&format!("0x{}", self.remove_0x_prefix(address)))?;
+            call_args.push(CallArg::Pure(bcs::to_bytes(&address)?));
+        }
+        
+        let mut builder = ProgrammableTransactionBuilder::new();
+        builder.move_call(
+            package_id,
+            Identifier::new("shares_trading")?,
+            Identifier::new(function)?,
+            vec![],
+            call_args,
+        )?;
+        let kind = TransactionKind::ProgrammableTransaction(builder.finish());
+        
+        // Nothing is executed, any sender can inspect
+        let params = json!([
+            SuiAddress::ZERO.to_string(),
+            BASE64_STANDARD.encode(bcs::to_bytes(&kind)?),
+            null,
+            null
+        ]);
+        let result = self.rpc_call("sui_devInspectTransactionBlock", params).await?;
+        decode_u64_return(result).map_err(|e| anyhow!("{} of {} failed: {:?}", function, deployment.object_id, e))
+    }
+    
+    /// Get shares on a Sui deployment
+    async fn get_sui_shares(&self, deployment: &str, subject: &str, user: &str) -> Result<u64> {
+        self.call_u64_view(deployment, "get_shares_balance", &[subject, user]).await
+    }
+    
+    /// Get the shares supply of a subject on a Sui deployment
+    async fn get_sui_supply(&self, deployment: &str, subject: &str) -> Result<u64> {
+        self.call_u64_view(deployment, "get_current_supply", &[subject]).await
+    }
+}
+
+/// Signature verification parameters trusting the JWKs of `sources` for zkLogin
+fn zklogin_verify_params(sources: &[ZkLoginJwkSource], test_env: bool) -> Result<VerifyParams> {
+    let mut jwks = ImHashMap::new();
+    let mut providers = Vec::new();
+    for source in sources {
+        let provider = OIDCProvider::from_iss(&source.iss)
+            .map_err(|e| anyhow!("Unsupported zkLogin issuer {}: {:?}", source.iss, e))?;
+        let json = std::fs::read(&source.path)
+            .map_err(|e| anyhow!("Cannot read JWK set {}: {}", source.path, e))?;
+        let keys = parse_jwks(&json, &provider, true)
+            .map_err(|e| anyhow!("Invalid JWK set {}: {:?}", source.path, e))?;
+        println!("Loaded {} zkLogin JWKs of {}", keys.len(), source.iss);
+        jwks.extend(keys);
+        providers.push(provider);
+    }
+    
+    let env = if test_env { ZkLoginEnv::Test } else { ZkLoginEnv::Prod };
+    Ok(VerifyParams::new(
+        jwks,
+        providers,
+        env,
+        true, // verify_legacy_zklogin_address
+        true, // accept_zklogin_in_multisig
+        false, // accept_passkey_in_multisig
+        Some(ZKLOGIN_MAX_EPOCH_UPPER_BOUND_DELTA),
+        true, // additional_multisig_checks
+    ))
+}
+
+/// Decode a base64 serialized signature: `flag || signature || public key` of an Ed25519,
+/// Secp256k1 or Secp256r1 key, a MultiSig or a zkLogin authenticator
+fn decode_signature(signature: &str) -> Result<GenericSignature, String> {
+    let bytes = BASE64_STANDARD.decode(signature)
+        .map_err(|e| format!("Cannot decode signature: {}", e))?;
+    GenericSignature::from_bytes(&bytes)
+        .map_err(|e| format!("Invalid signature: {}", e))
+}
+
+/// Address a signature claims to be from: derived from the public key, the multisig
+/// committee or the zkLogin address seed and issuer
+fn signer_address(signature: &GenericSignature) -> Result<SuiAddress, String> {
+    match signature {
+        GenericSignature::Signature(signature) => signature.to_public_key()
+            .map(|public_key| SuiAddress::from(&public_key))
+            .map_err(|e| format!("Invalid public key: {}", e)),
+        GenericSignature::MultiSig(multisig) => Ok(SuiAddress::from(multisig.get_pk())),
+        GenericSignature::ZkLoginAuthenticator(zklogin) => SuiAddress::try_from(zklogin)
+            .map_err(|e| format!("Invalid zkLogin inputs: {}", e)),
+        _ => Err("Unsupported signature scheme".to_string()),
+    }
+}
+
+/// Verify a wallet's `PersonalMessage` signature of `message` at `epoch` and return the
+/// signer address without 0x prefix
+fn verify_personal_message(message: &str, signature: &GenericSignature, params: &VerifyParams, epoch: u64) -> Result<String, String> {
+    let address = signer_address(signature)?;
+    signature.verify_user_authenticator_epoch(epoch, Some(ZKLOGIN_MAX_EPOCH_UPPER_BOUND_DELTA))
+        .map_err(|e| format!("Signature expired: {}", e))?;
+    
+    let intent_message = IntentMessage::new(
+        Intent::personal_message(),
+        PersonalMessage { message: message.as_bytes().to_vec() },
+    );
+    signature.verify_claims(&intent_message, address, params, Arc::new(VerifiedDigestCache::new_empty()))
+        .map_err(|e| format!("Signature verification failed: {}", e))?;
+    
+    Ok(address.to_string().trim_start_matches("0x").to_string())
+}
+
+/// Decode the single u64 returned by an inspected move call
+fn decode_u64_return(result: Value) -> Result<u64> {
+    let results: DevInspectResults = serde_json::from_value(result)?;
+    if let Some(error) = results.error {
+        return Err(anyhow!("Execution failed: {}", error));
     }
+    let (bytes, type_name) = results.results.first()
+        .and_then(|call| call.return_values.first())
+        .ok_or_else(|| anyhow!("No return value"))?;
+    if type_name != "u64" {
+        return Err(anyhow!("Expected a u64 return value, got {}", type_name));
+    }
+    Ok(bcs::from_bytes(bytes)?)
+}
+
+/// Move type of the Trade event emitted by a shares trading package version
+fn trade_event_type(package_id: &str) -> String {
+    format!("{}::shares_trading::Trade", package_id)
 }
 
 #[async_trait]
 impl Blockchain for SuiBlockchain {
-    fn get_name(&self) -> &'static str {
+    fn get_name(&self) -> &str {
         "sui"
     }
     
+    fn deployments(&self) -> Vec<String> {
+        self.deployments.iter().map(|deployment| deployment.object_id.clone()).collect()
+    }
+    
     async fn sync_events(&self, pool: &PgPool) -> Result<()> {
-        // Get last synced data (Sui uses cursor) and get metadata
-        let (last_cursor_num, metadata) = get_last_synced_block_with_metadata(pool, 0, self.get_name()).await?;
-        println!("last_cursor_num: {}", last_cursor_num);
-        println!("Metadata query result: {:?}", metadata);
-        
-        // Initialize cursor - prioritize using metadata
-        let mut cursor_str: Option<String> = if let Some(meta_str) = metadata {
-            println!("Found valid metadata: {}", meta_str);
-            // If there's valid metadata, use it to restore cursor
-            Some(meta_str)
-        } else {
-            None
-        };
-        
-        println!("Starting sync from cursor {:?} for {}", cursor_str, self.get_name());
+        match self.sync_mode {
+            SuiSyncMode::Events => self.sync_event_types(pool).await,
+            SuiSyncMode::Checkpoints => self.sync_checkpoints(pool).await,
+        }
+    }
+    
+    async fn backfill_range(&self, pool: &PgPool, from: u64, to: u64) -> Result<usize> {
+        let mut found = 0;
+        let mut start = from;
         
-        // Event sync loop
-        loop {
-            // Query events
-            match self.get_events(cursor_str.clone(), 100).await {
-                Ok(events) => {
-                    //println!("Found {} events for {} with cursor {:?}", events.data.len(), self.get_name(), cursor_str);
-                    
-                    // Process each event
-                    for event in &events.data {
-                        if let Err(e) = self.process_trade_event(&event.parsed_json, pool).await {
-                            println!("Error processing Sui trade event: {:?}", e);
-                        }
-                    }
-                    
-                    // Update cursor
-                    if let Some(next_cursor) = events.nextCursor {
-                        // Serialize EventID to JSON string
-                        let next_cursor_json = serde_json::to_string(&next_cursor).unwrap_or_default();
-                        cursor_str = Some(next_cursor_json.clone());
-                        
-                        // Serialize full EventID as JSON string to database
-                        // Use txDigest as numeric part (converted to u64), and full JSON in metadata field
-                        let tx_digest_hash = u64::from_str_radix(&next_cursor.tx_digest[0..16], 16).unwrap_or(0);
-                        
-                        // println!("Updating sync progress: tx_digest={}, eventSeq={}, hash={}, json={}",
-                        //     next_cursor.tx_digest, next_cursor.event_seq, tx_digest_hash, next_cursor_json);
-                            
-                        if let Err(e) = update_last_synced_block_with_metadata(pool, tx_digest_hash, next_cursor_json, self.get_name()).await {
-                            println!("Failed to update last synced cursor: {:?}", e);
-                        }
-                    } else if !events.hasNextPage {
-                        // No more events, wait for new events
-                        println!("No more events available for {}, waiting for new events...", self.get_name());
-                        tokio::time::sleep(Duration::from_secs(60)).await;
-                    }
-                },
-                Err(e) => {
-                    println!("Failed to query Sui events: {:?}", e);
-                    tokio::time::sleep(Duration::from_secs(10)).await;
-                }
+        while start <= to {
+            let end = std::cmp::min(start + CHECKPOINT_BATCH_SIZE - 1, to);
+            
+            let mut events = Vec::new();
+            for checkpoint in start..=end {
+                events.extend(self.get_checkpoint_trade_events(checkpoint).await?);
             }
+            let trades = self.to_indexed_trades(&events);
+            println!("Backfilling {} events in checkpoints {} to {} for {}", trades.len(), start, end, self.get_name());
             
-            // Brief rest, avoid too frequent requests
-            tokio::time::sleep(Duration::from_secs(1)).await;
+            commit_batch(pool, self.get_name(), &trades, None).await?;
+            found += trades.len();
+            start = end + 1;
         }
+        
+        Ok(found)
     }
     
-    fn verify_signature(&self, challenge: &str, signature: &str) -> Result<String, String> {
-        // Use sui-sdk library for signature verification
-        // Step 1: Decode Base64 format signature
-        let signature_bytes = match BASE64_STANDARD.decode(signature) {
-            Ok(bytes) => bytes,
-            Err(e) => return Err(format!("Cannot decode signature: {}", e)),
+    async fn verify_signature(&self, challenge: &str, signature: &str) -> Result<String, String> {
+        let signature = decode_signature(signature)?;
+        // Only zkLogin signatures, alone or in a multisig, depend on the epoch
+        let epoch = match &signature {
+            GenericSignature::Signature(_) => 0,
+            _ => self.current_epoch().await.map_err(|e| format!("Cannot get current epoch: {:?}", e))?,
         };
-        
-        // Now the challenge parameter is already the user's address, just return it directly
-        // This is just a temporary solution, long term should implement complete Sui signature verification logic
-        
-        Ok(challenge.to_string())
+        verify_personal_message(challenge, &signature, &self.verify_params, epoch)
+    }
+    
+    async fn get_shares_balance(&self, deployment: &str, subject: &str, user: &str) -> Result<u64> {
+        self.get_sui_shares(deployment, subject, user).await
     }
     
-    async fn get_shares_balance(&self, subject: &str, user: &str) -> Result<u64> {
-        self.get_sui_shares(subject, user).await
+    async fn get_current_supply(&self, deployment: &str, subject: &str) -> Result<Option<u64>> {
+        Ok(Some(self.get_sui_supply(deployment, subject).await?))
+    }
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event_id(tx_digest: &str, event_seq: &str) -> EventID {
+        EventID {
+            tx_digest: tx_digest.to_string(),
+            event_seq: event_seq.to_string(),
+        }
+    }
+
+    fn page(next_cursor: Option<EventID>, has_next_page: bool) -> SuiEventPage {
+        SuiEventPage {
+            data: Vec::new(),
+            nextCursor: next_cursor,
+            hasNextPage: has_next_page,
+        }
+    }
+
+    #[test]
+    fn test_page_state() {
+        let current = event_id("8Fb2xPuvDmP8vLH4Qb1jDxaJTd1iQFL7DNxhYSbfpGNW", "0");
+        let next = event_id("8Fb2xPuvDmP8vLH4Qb1jDxaJTd1iQFL7DNxhYSbfpGNW", "1");
+
+        assert_eq!(PageState::of(Some(&current), &page(Some(next.clone()), true)), PageState::Fetching);
+        assert_eq!(PageState::of(None, &page(Some(next.clone()), true)), PageState::Fetching);
+        assert_eq!(PageState::of(Some(&current), &page(Some(next), false)), PageState::CaughtUp);
+        assert_eq!(PageState::of(Some(&current), &page(None, false)), PageState::CaughtUp);
+
+        // More pages reported without moving the cursor must not spin
+        assert_eq!(PageState::of(Some(&current), &page(Some(current.clone()), true)), PageState::Stalled);
+        assert_eq!(PageState::of(Some(&current), &page(None, true)), PageState::Stalled);
+    }
+
+    #[test]
+    fn test_decode_trade() {
+        let trade = MoveTrade {
+            trader: SuiAddress::from_str("0x00000000000000000000000000000000000000000000000000000000000000a1").unwrap(),
+            subject: SuiAddress::from_str("0x00000000000000000000000000000000000000000000000000000000000000b2").unwrap(),
+            is_buy: true,
+            amount: 2,
+            price: 18_446_744_073_709_551_615,
+            protocol_fee: 5,
+            subject_fee: 6,
+            supply: 7,
+        };
+        let bytes = bcs::to_bytes(&trade).unwrap();
+        assert_eq!(bytes.len(), 32 + 32 + 1 + 5 * 8);
+        assert_eq!(MoveTrade::from_bcs(&BASE64_STANDARD.encode(&bytes), "base64").unwrap(), trade);
+        assert_eq!(MoveTrade::from_bcs(&bs58::encode(&bytes).into_string(), "base58").unwrap(), trade);
+        assert!(MoveTrade::from_bcs(&BASE64_STANDARD.encode(&bytes[1..]), "base64").is_err());
+
+        let json = SuiTradeEvent {
+            trader: trade.trader.to_string(),
+            subject: trade.subject.to_string(),
+            is_buy: true,
+            amount: "2".to_string(),
+            price: "18446744073709551615".to_string(),
+            protocol_fee: "5".to_string(),
+            subject_fee: "6".to_string(),
+            supply: "7".to_string(),
+        };
+        assert_eq!(MoveTrade::try_from(&json).unwrap(), trade);
+    }
+
+    #[test]
+    fn test_verify_personal_message() {
+        // Signed offline over the personal message intent of "7346290155"
+        let vectors = [
+            // Ed25519
+            (
+                "AKeQAHiSBQRbOaxKwhObiECGvhJ5xyVa/UrdXBmPvqHgCmEN3m/RV3GV31jsTMQIoEdgzSY0C31jdOqlp0F4dgYDoQe/884Qvh1w3RjnS8CZZ+TWMJulDV8d3IZkElUxuA==",
+                "160179a1565ea7cff27ead23f54cc7f50893bf58155cd7285156e57afa31c3ac",
+            ),
+            // Secp256k1
+            (
+                "ASUbgtZslxIkHtY1g9oj7dMYPDQgq1et1wPXrFEwfPKeUNXgANqDgjAKzaqBVIAzX8r/sa/gOKGy5I5NbQg6kFEDqLKf1gbICL6Wanoss0qyOjPxYHBMgppXd/K8+klnWfk=",
+                "f7b313ad298eca162ecdd3b0398b3248c9f8dbacd316f864f4a131798d574e88",
+            ),
+            // Secp256r1
+            (
+                "AiCtZwR1RETXFBUjjmVa7tmcpendeD94Rosxr1bg/AMge1+tYqGmR3NtR6VIEmXUESLTjyadWbc8py7cdDWI4A8D9NLFHCcfbFeQbi4oo8F998NLCzeY7cpP8LAPzYHlHxc=",
+                "09464c022b1ea51e77638765f05d76737ff2d11c1a96e10705311aa33eeba1dc",
+            ),
+        ];
+
+        let params = zklogin_verify_params(&[], false).unwrap();
+        for (signature, address) in vectors {
+            let signature = decode_signature(signature).unwrap();
+            assert_eq!(verify_personal_message("7346290155", &signature, &params, 0).unwrap(), address);
+            // Signed for another Telegram user
+            assert!(verify_personal_message("7346290156", &signature, &params, 0).is_err());
+        }
+        assert!(decode_signature("not base64").is_err());
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_verify_multisig_personal_message() {
+        // 1-of-2 committee of the Ed25519 and Secp256k1 keys above, signed by the Ed25519 member
+        let signature = decode_signature(
+            "AwEAp5AAeJIFBFs5rErCE5uIQIa+EnnHJVr9St1cGY++oeAKYQ3eb9FXcZXfWOxMxAigR2DNJjQLfWN06qWnQXh2BgEAAgADoQe/884Qvh1w3RjnS8CZZ+TWMJulDV8d3IZkElUxuAEBA6iyn9YGyAi+lmp6LLNKsjoz8WBwTIKaV3fyvPpJZ1n5AQEA"
+        ).unwrap();
+        let params = zklogin_verify_params(&[], false).unwrap();
+
+        assert_eq!(
+            verify_personal_message("7346290155", &signature, &params, 0).unwrap(),
+            "564b2183fd948a3e6de2e8274444b011e962109511ccc0a3e66b92606b6a3d94"
+        );
+        assert!(verify_personal_message("7346290156", &signature, &params, 0).is_err());
+    }
+
+    #[test]
+    fn test_decode_u64_return() {
+        let result = json!({
+            "effects": { "status": { "status": "success" } },
+            "results": [{ "returnValues": [[[42, 0, 0, 0, 0, 0, 0, 0], "u64"]] }]
+        });
+        assert_eq!(decode_u64_return(result).unwrap(), 42);
+
+        let aborted = json!({ "error": "MoveAbort(..., 3) in command 0", "results": [] });
+        assert!(decode_u64_return(aborted).is_err());
+        assert!(decode_u64_return(json!({ "results": [{ "returnValues": [] }] })).is_err());
+        assert!(decode_u64_return(json!({ "results": [{ "returnValues": [[[1], "bool"]] }] })).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::Result;
+use sqlx::types::BigDecimal;
+use sqlx::{PgConnection, PgPool};
+
+use crate::block_chain::Blockchain;
+use crate::block_chain::registry::ChainRegistry;
+use crate::block_chain::trade::IndexedTrade;
+use crate::db::models::{SubjectSupply, SupplyGap};
+use crate::db::operations::{get_pending_supply_gaps, get_subject_supply, record_supply_gap, save_subject_supply, update_supply_gap_status};
+
+/// How often queued supply gaps are re-scanned
+const RESCAN_INTERVAL: Duration = Duration::from_secs(60);
+/// Supply gaps re-scanned per pass
+const RESCAN_BATCH_SIZE: i64 = 10;
+/// Larger ranges are left to `reindex --from --to`
+const MAX_RESCAN_RANGE: i64 = 100_000;
+
+/// Supply expected after `trade` when the subject's supply was `previous`
+fn expected_supply(previous: &BigDecimal, trade: &IndexedTrade) -> BigDecimal {
+    if trade.is_buy {
+        previous + &trade.share_amount
+    } else {
+        previous - &trade.share_amount
+    }
+}
+
+/// Whether `trade` happened after the latest event of the projection, `None` when their order is unknown
+fn is_after(trade: &IndexedTrade, previous: &SubjectSupply) -> Option<bool> {
+    if trade.key.tx_hash == previous.last_tx_hash && trade.key.log_index == previous.last_log_index {
+        return Some(false);
+    }
+    match (trade.key.block_number, previous.last_block_number) {
+        // Log indexes are unique within an EVM block
+        (Some(block), Some(last_block)) => Some((block, trade.key.log_index) > (last_block, previous.last_log_index)),
+        _ => match (trade.details.checkpoint, previous.last_checkpoint) {
+            // Sui events don't tell their transaction's position in the checkpoint, but confirmed
+            // events are checked in chain order, so another event of the same checkpoint is later
+            (Some(checkpoint), Some(last_checkpoint)) => Some(checkpoint >= last_checkpoint),
+            _ => None,
+        },
+    }
+}
+
+/// Blocks (Monad) or checkpoints (Sui) between the previous event and `trade`, where a missed event would be
+fn rescan_range(trade: &IndexedTrade, previous: &SubjectSupply) -> Option<(i64, i64)> {
+    match (previous.last_block_number, trade.key.block_number) {
+        (Some(from), Some(to)) => Some((from, to)),
+        _ => previous.last_checkpoint.zip(trade.details.checkpoint),
+    }
+}
+
+/// Check that a confirmed event's supply continues the subject's projected supply, then
+/// advance the projection. A mismatch means an event in between was missed or duplicated,
+/// it is recorded in `supply_gaps` and the range since the previous event is queued for a re-scan.
+/// Only call this for events of a confirmed range, in chain order: streamed events arrive out of
+/// order and would be reported as gaps.
+pub async fn check_supply_continuity(conn: &mut PgConnection, chain_type: &str, trade: &IndexedTrade) -> Result<()> {
+    if let Some(previous) = get_subject_supply(conn, chain_type, &trade.deployment, &trade.subject).await? {
+        match is_after(trade, &previous) {
+            Some(true) => {},
+            // Already checked, or late, e.g. after a reorg: don't move the projection back
+            Some(false) => return Ok(()),
+            None => {
+                println!(
+                    "Skipping supply check of {} event {}:{}: no position to order it by",
+                    chain_type, trade.key.tx_hash, trade.key.log_index
+                );
+                return Ok(());
+            }
+        }
+
+        let expected = expected_supply(&previous.supply, trade);
+        if expected != trade.details.supply {
+            println!(
+                "Supply gap for {} subject {}: event {}:{} has supply {}, expected {}",
+                chain_type, trade.subject, trade.key.tx_hash, trade.key.log_index, trade.details.supply, expected
+            );
+            record_supply_gap(conn, chain_type, &trade.deployment, &trade.subject, &expected, &trade.details.supply, &trade.key, rescan_range(trade, &previous)).await?;
+        }
+    }
+
+    // The event's supply comes from the chain, continue from it either way
+    save_subject_supply(conn, chain_type, &trade.deployment, &trade.subject, &trade.details.supply, &trade.key, trade.details.checkpoint).await?;
+    Ok(())
+}
+
+/// Re-fetch the ranges of queued supply gaps so missed events get ingested
+pub async fn run_supply_rescans(chains: Arc<ChainRegistry>, pool: PgPool) {
+    // Gaps of disabled chains stay pending until the chain is enabled again
+    let chain_types: Vec<String> = chains.chain_types().into_iter().map(str::to_string).collect();
+    let mut ticker = tokio::time::interval(RESCAN_INTERVAL);
+
+    loop {
+        ticker.tick().await;
+        let gaps = match get_pending_supply_gaps(&pool, &chain_types, RESCAN_BATCH_SIZE).await {
+            Ok(gaps) => gaps,
+            Err(e) => {
+                println!("Failed to get pending supply gaps: {:?}", e);
+                continue;
+            }
+        };
+
+        for gap in gaps {
+            let Some(blockchain) = chains.get(&gap.chain_type) else {
+                continue;
+            };
+            let status = rescan_gap(blockchain.as_ref(), &pool, &gap).await;
+            if let Err(e) = update_supply_gap_status(&pool, gap.id, status).await {
+                println!("Failed to update supply gap {}: {:?}", gap.id, e);
+            }
+        }
+    }
+}
+
+/// Log whether the re-scanned subject's projected supply now matches its on-chain supply.
+/// Trades that are not confirmed yet can still make them differ.
+async fn compare_onchain_supply(blockchain: &dyn Blockchain, pool: &PgPool, gap: &SupplyGap) -> Result<()> {
+    let Some(onchain) = blockchain.get_current_supply(&gap.deployment, &gap.subject).await? else {
+        return Ok(());
+    };
+    let mut conn = pool.acquire().await?;
+    let Some(projected) = get_subject_supply(&mut conn, &gap.chain_type, &gap.deployment, &gap.subject).await? else {
+        return Ok(());
+    };
+
+    if projected.supply == BigDecimal::from(onchain) {
+        println!("Supply gap {} closed: {} subject {} supply {} matches the chain", gap.id, gap.chain_type, gap.subject, onchain);
+    } else {
+        println!("Supply gap {} still open: {} subject {} supply is {} on chain, {} projected", gap.id, gap.chain_type, gap.subject, onchain, projected.supply);
+    }
+    Ok(())
+}
+
+async fn rescan_gap(blockchain: &dyn Blockchain, pool: &PgPool, gap: &SupplyGap) -> &'static str {
+    let (from, to) = match gap.rescan_from.zip(gap.rescan_to) {
+        Some((from, to)) if from <= to && to - from <= MAX_RESCAN_RANGE => (from, to),
+        _ => {
+            println!("Skipping re-scan of supply gap {}: range {:?} to {:?} unknown or too large", gap.id, gap.rescan_from, gap.rescan_to);
+            return "skipped";
+        }
+    };
+
+    match blockchain.backfill_range(pool, from as u64, to as u64).await {
+        Ok(found) => {
+            println!("Re-scanned {} range {} to {} for supply gap {}, {} events on chain", gap.chain_type, from, to, gap.id, found);
+            if let Err(e) = compare_onchain_supply(blockchain, pool, gap).await {
+                println!("Failed to compare supply of gap {} with the chain: {:?}", gap.id, e);
+            }
+            "rescanned"
+        },
+        Err(e) => {
+            println!("Failed to re-scan supply gap {}: {:?}", gap.id, e);
+            "failed"
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::{TradeEventDetails, TradeEventKey};
+
+    fn monad_trade(block_number: i64, log_index: i64, is_buy: bool, amount: i64, supply: i64) -> IndexedTrade {
+        IndexedTrade {
+            key: TradeEventKey {
+                tx_hash: format!("0x{:x}", block_number),
+                log_index,
+                block_number: Some(block_number),
+            },
+            trader: "trader".to_string(),
+            subject: "subject".to_string(),
+            is_buy,
+            share_amount: BigDecimal::from(amount),
+            deployment: "0x01".to_string(),
+            details: TradeEventDetails {
+                price: BigDecimal::from(0),
+                protocol_fee: BigDecimal::from(0),
+                subject_fee: BigDecimal::from(0),
+                supply: BigDecimal::from(supply),
+                timestamp_ms: None,
+                block_hash: None,
+                checkpoint: None,
+                raw: serde_json::Value::Null,
+            },
+        }
+    }
+
+    #[test]
+    fn test_supply_continuity() {
+        let previous = SubjectSupply {
+            supply: BigDecimal::from(10),
+            last_tx_hash: "0x64".to_string(),
+            last_block_number: Some(100),
+            last_log_index: 2,
+            last_checkpoint: None,
+        };
+
+        let buy = monad_trade(105, 0, true, 3, 13);
+        assert_eq!(expected_supply(&previous.supply, &buy), buy.details.supply);
+        let sell = monad_trade(105, 0, false, 3, 7);
+        assert_eq!(expected_supply(&previous.supply, &sell), sell.details.supply);
+
+        // A buy of 2 in between was missed
+        let after_gap = monad_trade(105, 0, true, 3, 15);
+        assert_ne!(expected_supply(&previous.supply, &after_gap), after_gap.details.supply);
+        assert_eq!(rescan_range(&after_gap, &previous), Some((100, 105)));
+
+        assert_eq!(is_after(&monad_trade(100, 1, true, 1, 1), &previous), Some(false));
+        assert_eq!(is_after(&monad_trade(100, 3, true, 1, 1), &previous), Some(true));
+        // The projection's own event, e.g. streamed before it was confirmed
+        assert_eq!(is_after(&monad_trade(100, 2, true, 1, 1), &previous), Some(false));
+    }
+
+    #[test]
+    fn test_sui_event_order() {
+        let previous = SubjectSupply {
+            supply: BigDecimal::from(10),
+            last_tx_hash: "digest_a".to_string(),
+            last_block_number: None,
+            last_log_index: 0,
+            last_checkpoint: Some(500),
+        };
+        let sui_trade = |tx_hash: &str, event_seq: i64, checkpoint: Option<i64>| {
+            let mut trade = monad_trade(0, event_seq, true, 1, 11);
+            trade.key.tx_hash = tx_hash.to_string();
+            trade.key.block_number = None;
+            trade.details.checkpoint = checkpoint;
+            trade
+        };
+
+        assert_eq!(is_after(&sui_trade("digest_a", 0, Some(500)), &previous), Some(false));
+        assert_eq!(is_after(&sui_trade("digest_a", 1, Some(500)), &previous), Some(true));
+        assert_eq!(is_after(&sui_trade("digest_b", 0, Some(500)), &previous), Some(true));
+        assert_eq!(is_after(&sui_trade("digest_b", 0, Some(499)), &previous), Some(false));
+        assert_eq!(is_after(&sui_trade("digest_b", 0, None), &previous), None);
+    }
+}
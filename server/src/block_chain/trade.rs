@@ -0,0 +1,365 @@
+use std::collections::HashSet;
+use anyhow::{Result, anyhow};
+use sqlx::types::BigDecimal;
+use sqlx::{Connection, PgConnection, PgPool};
+use teloxide::Bot;
+use teloxide::prelude::{Requester, UserId};
+use teloxide::types::ChatPermissions;
+
+use crate::block_chain::dead_letter::MAX_ATTEMPTS;
+use crate::block_chain::outbox::enqueue_access_change;
+use crate::block_chain::supply::check_supply_continuity;
+use crate::db::models::{SuiCursor, TradeEventDetails, TradeEventKey};
+use crate::db::operations::{discard_orphaned_failed_events, get_trade_event_keys_in_blocks, process_buy_trade, process_sell_trade, prune_indexed_blocks, record_failed_event, record_trade_event, revert_trade_event, save_indexed_block, save_sui_cursor, update_last_synced_block};
+
+/// Number of blocks behind the cursor whose hashes are kept for reorg detection
+pub const INDEXED_BLOCK_HISTORY: u64 = 256;
+
+/// Trade event normalized from a Monad log or a Sui event
+#[derive(Clone, Debug)]
+pub struct IndexedTrade {
+    pub key: TradeEventKey,
+    /// Trader address, without 0x prefix
+    pub trader: String,
+    /// Subject address, without 0x prefix
+    pub subject: String,
+    pub is_buy: bool,
+    pub share_amount: BigDecimal,
+    /// Market the trade happened on: EVM contract address or Sui SharesTrading object id
+    pub deployment: String,
+    pub details: TradeEventDetails,
+}
+
+/// Sync progress persisted together with a batch
+#[derive(Clone, Debug)]
+pub enum SyncCursor {
+    Block(u64),
+    /// Position in the Sui event query pagination
+    SuiEvent(SuiCursor),
+    /// Block cursor that also records the block hash for reorg detection.
+    /// Ledger events between `range_start` and `number` missing from the batch were
+    /// applied from blocks that did not make it into the chain and are reverted.
+    BlockWithHash {
+        range_start: u64,
+        number: u64,
+        hash: String,
+        parent_hash: String,
+    },
+}
+
+/// Telegram permission change caused by a trade, queued in the outbox in the same transaction
+/// as the balance change and executed by the outbox worker.
+/// The bot is identified by its `agent_name`, its current token is looked up when the change runs.
+#[derive(Clone, Debug)]
+pub enum AccessChange {
+    Grant {
+        agent_name: String,
+        chat_group_id: String,
+        telegram_id: String,
+    },
+    Revoke {
+        agent_name: String,
+        chat_group_id: String,
+        telegram_id: String,
+    },
+}
+
+/// Permissions of a member holding shares of the group's subject
+pub fn member_permissions() -> ChatPermissions {
+    ChatPermissions::empty()
+        | ChatPermissions::SEND_MESSAGES
+        | ChatPermissions::SEND_MEDIA_MESSAGES
+        | ChatPermissions::SEND_OTHER_MESSAGES
+        | ChatPermissions::SEND_POLLS
+        | ChatPermissions::ADD_WEB_PAGE_PREVIEWS
+}
+
+impl AccessChange {
+    pub async fn execute(&self, bot_token: &str) -> Result<()> {
+        let (chat_group_id, telegram_id, permissions) = match self {
+            AccessChange::Grant { chat_group_id, telegram_id, .. } => (chat_group_id, telegram_id, member_permissions()),
+            AccessChange::Revoke { chat_group_id, telegram_id, .. } => (chat_group_id, telegram_id, ChatPermissions::empty()),
+        };
+        let user_id: u64 = telegram_id.parse()
+            .map_err(|e| anyhow!("Invalid telegram id {}: {:?}", telegram_id, e))?;
+
+        let bot = Bot::new(bot_token);
+        bot.restrict_chat_member(chat_group_id.clone(), UserId(user_id), permissions).await?;
+        Ok(())
+    }
+}
+
+/// Apply every trade of a batch, queue the Telegram access changes they cause in the outbox
+/// and advance the cursor in a single transaction.
+/// Either the whole range is durably applied and the cursor moves, or nothing changes, so
+/// no access change is lost between the balance update and the Telegram call.
+/// A trade that fails to apply is rolled back on its own and moved to the dead-letter
+/// queue, so it doesn't block the rest of the chain.
+pub async fn commit_batch(
+    pool: &PgPool,
+    chain_type: &str,
+    trades: &[IndexedTrade],
+    cursor: Option<SyncCursor>,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    // Batches that advance a cursor cover a confirmed range in chain order, only those can be
+    // checked for supply gaps. Events already applied from the stream are checked here too.
+    let confirmed = cursor.is_some();
+
+    for trade in trades {
+        let mut savepoint = tx.begin().await?;
+        let mut applied = apply_trade(&mut savepoint, chain_type, trade).await;
+        if applied.is_ok() && confirmed {
+            applied = check_supply_continuity(&mut savepoint, chain_type, trade).await;
+        }
+        match applied {
+            Ok(()) => savepoint.commit().await?,
+            Err(e) => {
+                savepoint.rollback().await?;
+                println!("Failed to apply {} trade event {}:{}, moving it to the dead-letter queue: {:?}", chain_type, trade.key.tx_hash, trade.key.log_index, e);
+                record_failed_event(
+                    &mut tx,
+                    &trade.key,
+                    &trade.trader,
+                    &trade.subject,
+                    trade.is_buy,
+                    &trade.share_amount,
+                    &trade.details,
+                    &format!("{:#}", e),
+                    MAX_ATTEMPTS,
+                    chain_type,
+                    &trade.deployment,
+                ).await?;
+            }
+        }
+    }
+
+    match cursor {
+        Some(SyncCursor::Block(block)) => update_last_synced_block(&mut tx, block, chain_type).await?,
+        Some(SyncCursor::SuiEvent(sui_cursor)) => save_sui_cursor(&mut tx, &sui_cursor, chain_type).await?,
+        Some(SyncCursor::BlockWithHash { range_start, number, hash, parent_hash }) => {
+            revert_orphaned_trades(&mut tx, chain_type, range_start, number, trades).await?;
+            update_last_synced_block(&mut tx, number, chain_type).await?;
+            save_indexed_block(&mut tx, chain_type, number, &hash, &parent_hash).await?;
+            prune_indexed_blocks(&mut tx, chain_type, number.saturating_sub(INDEXED_BLOCK_HISTORY)).await?;
+        },
+        None => {}
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Revert ledger and dead-lettered events of a confirmed block range that are not part of the canonical batch
+async fn revert_orphaned_trades(
+    conn: &mut PgConnection,
+    chain_type: &str,
+    from_block: u64,
+    to_block: u64,
+    trades: &[IndexedTrade],
+) -> Result<()> {
+    let canonical: HashSet<(&str, i64)> = trades.iter()
+        .map(|trade| (trade.key.tx_hash.as_str(), trade.key.log_index))
+        .collect();
+
+    for (tx_hash, log_index) in get_trade_event_keys_in_blocks(conn, chain_type, from_block, to_block).await? {
+        if !canonical.contains(&(tx_hash.as_str(), log_index)) {
+            println!("Reverting orphaned {} trade event {}:{}", chain_type, tx_hash, log_index);
+            revert_trade_event(conn, chain_type, &tx_hash, log_index).await?;
+        }
+    }
+
+    let (tx_hashes, log_indexes): (Vec<String>, Vec<i64>) = trades.iter()
+        .map(|trade| (trade.key.tx_hash.clone(), trade.key.log_index))
+        .unzip();
+    let discarded = discard_orphaned_failed_events(conn, chain_type, from_block, to_block, &tx_hashes, &log_indexes).await?;
+    if discarded > 0 {
+        println!("Discarded {} orphaned {} dead-lettered events", discarded, chain_type);
+    }
+
+    Ok(())
+}
+
+/// Re-evaluate a holder's group access after their balance was corrected outside of a trade
+pub async fn reevaluate_access(
+    conn: &mut PgConnection,
+    chain_type: &str,
+    deployment: &str,
+    trader: &str,
+    subject: &str,
+    balance: &BigDecimal,
+) -> Result<Option<AccessChange>> {
+    let user_mapping = sqlx::query!(
+        "SELECT telegram_id, is_banned FROM user_mappings WHERE address = $1 AND chain_type = $2",
+        trader,
+        chain_type
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(user) = user_mapping else {
+        return Ok(None);
+    };
+
+    let holds_shares = *balance > BigDecimal::from(0);
+    if holds_shares != user.is_banned {
+        // Access already matches the balance
+        return Ok(None);
+    }
+
+    let bot_info = sqlx::query!(
+        "SELECT agent_name, chat_group_id FROM telegram_bots WHERE subject_address = $1 AND chain_type = $2 AND deployment = $3",
+        subject,
+        chain_type,
+        deployment
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(bot_info) = bot_info else {
+        return Ok(None);
+    };
+
+    sqlx::query!(
+        "UPDATE user_mappings SET is_banned = $1 WHERE address = $2 AND chain_type = $3",
+        !holds_shares,
+        trader,
+        chain_type
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(Some(if holds_shares {
+        AccessChange::Grant {
+            agent_name: bot_info.agent_name,
+            chat_group_id: bot_info.chat_group_id,
+            telegram_id: user.telegram_id,
+        }
+    } else {
+        AccessChange::Revoke {
+            agent_name: bot_info.agent_name,
+            chat_group_id: bot_info.chat_group_id,
+            telegram_id: user.telegram_id,
+        }
+    }))
+}
+
+/// Apply a single trade and queue the access change it causes
+async fn apply_trade(conn: &mut PgConnection, chain_type: &str, trade: &IndexedTrade) -> Result<()> {
+    if let Some(change) = apply_balance_change(conn, chain_type, trade).await? {
+        let dedup_key = format!("trade:{}:{}:{}", chain_type, trade.key.tx_hash, trade.key.log_index);
+        enqueue_access_change(conn, &change, chain_type, &dedup_key).await?;
+    }
+    Ok(())
+}
+
+/// Update holdings for a single trade, skipping events already recorded in the ledger
+async fn apply_balance_change(conn: &mut PgConnection, chain_type: &str, trade: &IndexedTrade) -> Result<Option<AccessChange>> {
+    println!("Processing {} Trade event: {:?}", chain_type, trade);
+
+    if !record_trade_event(conn, &trade.key, &trade.trader, &trade.subject, trade.is_buy, &trade.share_amount, &trade.details, chain_type, &trade.deployment).await? {
+        println!("Trade event {}:{} already ingested, skipping", trade.key.tx_hash, trade.key.log_index);
+        return Ok(None);
+    }
+
+    if trade.is_buy {
+        // Buy operation, increase shares
+        process_buy_trade(
+            conn,
+            trade.trader.clone(),
+            trade.subject.clone(),
+            trade.share_amount.clone(),
+            chain_type,
+            &trade.deployment,
+        ).await?;
+
+        // Check if user is banned
+        let user_mapping = sqlx::query!(
+            "SELECT telegram_id, is_banned FROM user_mappings WHERE address = $1 AND chain_type = $2",
+            trade.trader,
+            chain_type
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        if let Some(user) = user_mapping {
+            if user.is_banned {
+                let user_share = sqlx::query!(
+                    "SELECT share_amount FROM trades WHERE trader = $1 AND subject = $2 AND chain_type = $3 AND deployment = $4",
+                    trade.trader,
+                    trade.subject,
+                    chain_type,
+                    trade.deployment
+                )
+                .fetch_optional(&mut *conn)
+                .await?;
+
+                if let Some(share) = user_share {
+                    if share.share_amount > BigDecimal::from(0) {
+                        let bot_info = sqlx::query!(
+                            "SELECT agent_name, chat_group_id FROM telegram_bots WHERE subject_address = $1 AND chain_type = $2 AND deployment = $3",
+                            trade.subject,
+                            chain_type,
+                            trade.deployment
+                        )
+                        .fetch_optional(&mut *conn)
+                        .await?;
+
+                        if let Some(bot_info) = bot_info {
+                            return Ok(Some(AccessChange::Grant {
+                                agent_name: bot_info.agent_name,
+                                chat_group_id: bot_info.chat_group_id,
+                                telegram_id: user.telegram_id,
+                            }));
+                        }
+                    }
+                }
+            }
+        }
+    } else {
+        // Sell operation, decrease shares
+        println!("Trader {} sell {} shares of subject {}", trade.trader, trade.share_amount, trade.subject);
+        let (should_ban, telegram_id_opt) = process_sell_trade(
+            conn,
+            trade.trader.clone(),
+            trade.subject.clone(),
+            trade.share_amount.clone(),
+            chain_type,
+            &trade.deployment,
+        ).await?;
+
+        if should_ban {
+            if let Some(telegram_id) = telegram_id_opt {
+                println!("User {} has 0 shares for {}, banning user", trade.trader, trade.subject);
+
+                // Get the bot token and chat group id from telegram_bots table for this subject
+                let bot_info = sqlx::query!(
+                    "SELECT agent_name, chat_group_id FROM telegram_bots WHERE subject_address = $1 AND chain_type = $2 AND deployment = $3",
+                    trade.subject,
+                    chain_type,
+                    trade.deployment
+                )
+                .fetch_optional(&mut *conn)
+                .await?;
+
+                if let Some(bot_info) = bot_info {
+                    sqlx::query!(
+                        "UPDATE user_mappings SET is_banned = true WHERE address = $1 AND chain_type = $2",
+                        trade.trader,
+                        chain_type
+                    )
+                    .execute(&mut *conn)
+                    .await?;
+
+                    return Ok(Some(AccessChange::Revoke {
+                        agent_name: bot_info.agent_name,
+                        chat_group_id: bot_info.chat_group_id,
+                        telegram_id,
+                    }));
+                } else {
+                    println!("No telegram bot info found for subject {}", trade.subject);
+                }
+            }
+        }
+    }
+
+    Ok(None)
+}
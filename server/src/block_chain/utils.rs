@@ -1,28 +1,4 @@
-use ethers::{
-    prelude::*,
-    utils::hash_message,
-};
-use ethers::utils::hex;
-
-// Verify signature
-pub fn verify_signature(
-    challenge: &str,
-    signature: &str,
-) -> Result<Address, String> {
-    let sig_bytes = hex::decode(signature)
-        .map_err(|e| format!("Invalid signature hex: {}", e))?;
-
-    if sig_bytes.len() != 65 {
-        return Err("Signature must be 65 bytes".into());
-    }
-
-    let message_hash = hash_message(challenge);
-    let signature = Signature::try_from(sig_bytes.as_slice()).map_err(|e| format!("Invalid signature: {}!",e))?;
-    let recovered_address = signature
-        .recover(message_hash)
-        .map_err(|e| format!("Recovery failed: {}", e))?;
-    Ok(recovered_address)
-}
+use ethers::prelude::*;
 
 // Define Trade event structure
 #[derive(Debug, EthEvent)]
@@ -128,7 +104,7 @@ pub const TRADE_ABI: &str = r#"[{
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ethers::utils::keccak256;
+    use ethers::utils::{hex, keccak256};
     
     #[test]
     fn test_keccak256_hash() {
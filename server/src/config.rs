@@ -0,0 +1,251 @@
+use std::env;
+
+use crate::block_chain::registry::SUI_CHAIN_TYPE;
+
+/// EVM network running a shares contract, its name is the chain type stored with its data
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvmNetworkConfig {
+    pub name: String,
+    pub chain_id: u64,
+    pub rpc: Vec<String>,
+    pub ws_rpc: Option<String>,
+    /// Shares contracts indexed on the network, the first one owns data indexed before
+    /// deployments were tracked
+    pub contracts: Vec<String>,
+    pub start_block: u64,
+    pub confirmations: u64,
+}
+
+/// Sui SharesTrading shared object and the packages whose Trade events belong to it
+#[derive(Clone, Debug, PartialEq)]
+pub struct SuiDeploymentConfig {
+    /// SharesTrading object id, identifies the deployment
+    pub object_id: String,
+    /// Package versions emitting its Trade events, oldest first. The latest one is called
+    /// for balances, the first one owns the event cursor saved before deployments were tracked.
+    pub package_ids: Vec<String>,
+}
+
+/// JWK set of an OpenID provider whose zkLogin signatures are accepted
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZkLoginJwkSource {
+    /// Issuer as it appears in the JWT, e.g. https://accounts.google.com
+    pub iss: String,
+    /// File holding the provider's JWK set as served by its `jwks_uri`
+    pub path: String,
+}
+
+/// `SUI_ZKLOGIN_JWKS` lists `<issuer>=<path>` entries separated by `;`
+pub fn parse_zklogin_jwk_sources(value: &str) -> Vec<ZkLoginJwkSource> {
+    value.split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (iss, path) = entry.split_once('=')
+                .unwrap_or_else(|| panic!("SUI_ZKLOGIN_JWKS entry {} must be <issuer>=<path>", entry));
+            ZkLoginJwkSource {
+                iss: iss.trim().to_string(),
+                path: path.trim().to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Split a comma separated list of RPC endpoints
+pub fn parse_url_list(value: &str) -> Vec<String> {
+    value.split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Load the indexed EVM networks from the environment
+pub fn load_evm_networks() -> Vec<EvmNetworkConfig> {
+    parse_evm_networks(|key| env::var(key).ok())
+}
+
+/// `EVM_NETWORKS` lists network names, each configured with variables prefixed by its
+/// upper-cased name, e.g. `BASE_SEPOLIA_RPC` for `base_sepolia`. Without it the single
+/// `monad` network is configured from the unprefixed `CHAIN_*` variables, if `CHAIN_RPC` is set.
+fn parse_evm_networks(var: impl Fn(&str) -> Option<String>) -> Vec<EvmNetworkConfig> {
+    let Some(names) = var("EVM_NETWORKS") else {
+        if var("CHAIN_RPC").is_none() {
+            return Vec::new();
+        }
+        return vec![parse_evm_network(&var, "monad", |suffix| match suffix {
+            "CONTRACT" => "SHARES_CONTRACT_ADDRESS".to_string(),
+            "CONFIRMATIONS" => "CHAIN_CONFIRMATIONS".to_string(),
+            "CHAIN_ID" | "START_BLOCK" => suffix.to_string(),
+            _ => format!("CHAIN_{}", suffix),
+        })];
+    };
+
+    let mut networks: Vec<EvmNetworkConfig> = Vec::new();
+    for name in names.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        let name = name.to_lowercase();
+        if name == SUI_CHAIN_TYPE {
+            panic!("EVM network name {} is reserved", name);
+        }
+        if networks.iter().any(|network| network.name == name) {
+            panic!("EVM network {} configured twice", name);
+        }
+        let prefix = name.to_uppercase().replace('-', "_");
+        networks.push(parse_evm_network(&var, &name, |suffix| format!("{}_{}", prefix, suffix)));
+    }
+    networks
+}
+
+/// Load the indexed Sui deployments from the environment
+pub fn load_sui_deployments() -> Vec<SuiDeploymentConfig> {
+    parse_sui_deployments(|key| env::var(key).ok())
+}
+
+/// `SUI_DEPLOYMENTS` lists `<object id>:<package id>[,<package id>...]` entries separated by `;`.
+/// Without it a single deployment is configured from `SUI_SHARES_TRADING_OBJECT_ID` and `SUI_CONTRACT`.
+fn parse_sui_deployments(var: impl Fn(&str) -> Option<String>) -> Vec<SuiDeploymentConfig> {
+    let Some(entries) = var("SUI_DEPLOYMENTS") else {
+        let Some(package_id) = var("SUI_CONTRACT").filter(|package_id| !package_id.is_empty()) else {
+            return Vec::new();
+        };
+        let object_id = var("SUI_SHARES_TRADING_OBJECT_ID")
+            .expect("SUI_SHARES_TRADING_OBJECT_ID not set");
+        return vec![SuiDeploymentConfig {
+            object_id: object_id.to_lowercase(),
+            package_ids: vec![package_id.to_lowercase()],
+        }];
+    };
+
+    let mut deployments: Vec<SuiDeploymentConfig> = Vec::new();
+    for entry in entries.split(';').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (object_id, package_ids) = entry.split_once(':')
+            .unwrap_or_else(|| panic!("SUI_DEPLOYMENTS entry {} must be <object id>:<package ids>", entry));
+        let deployment = SuiDeploymentConfig {
+            object_id: object_id.trim().to_lowercase(),
+            package_ids: parse_url_list(&package_ids.to_lowercase()),
+        };
+        if deployment.package_ids.is_empty() {
+            panic!("SUI_DEPLOYMENTS entry {} has no package id", entry);
+        }
+        // Events are attributed to a deployment by their package
+        let shared = deployments.iter()
+            .flat_map(|other| &other.package_ids)
+            .find(|package_id| deployment.package_ids.contains(package_id));
+        if let Some(package_id) = shared {
+            panic!("Sui package {} is listed for several deployments", package_id);
+        }
+        deployments.push(deployment);
+    }
+    deployments
+}
+
+fn parse_evm_network(
+    var: &impl Fn(&str) -> Option<String>,
+    name: &str,
+    key: impl Fn(&str) -> String,
+) -> EvmNetworkConfig {
+    let required = |suffix: &str| {
+        let key = key(suffix);
+        var(&key).unwrap_or_else(|| panic!("{} not set", key))
+    };
+    let number = |suffix: &str, value: String| -> u64 {
+        value.parse().unwrap_or_else(|_| panic!("{} must be a number", key(suffix)))
+    };
+
+    EvmNetworkConfig {
+        name: name.to_string(),
+        chain_id: number("CHAIN_ID", required("CHAIN_ID")),
+        rpc: parse_url_list(&required("RPC")),
+        ws_rpc: var(&key("WS_RPC")).filter(|url| !url.is_empty()),
+        contracts: parse_url_list(&required("CONTRACT")),
+        start_block: number("START_BLOCK", required("START_BLOCK")),
+        confirmations: var(&key("CONFIRMATIONS"))
+            .map(|value| number("CONFIRMATIONS", value))
+            .unwrap_or(3),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn test_parse_evm_networks() {
+        assert!(parse_evm_networks(vars(&[])).is_empty());
+
+        let legacy = parse_evm_networks(vars(&[
+            ("CHAIN_ID", "10143"),
+            ("CHAIN_RPC", "https://a, https://b"),
+            ("SHARES_CONTRACT_ADDRESS", "0x01"),
+            ("START_BLOCK", "100"),
+        ]));
+        assert_eq!(legacy, vec![EvmNetworkConfig {
+            name: "monad".to_string(),
+            chain_id: 10143,
+            rpc: vec!["https://a".to_string(), "https://b".to_string()],
+            ws_rpc: None,
+            contracts: vec!["0x01".to_string()],
+            start_block: 100,
+            confirmations: 3,
+        }]);
+
+        let networks = parse_evm_networks(vars(&[
+            ("EVM_NETWORKS", "monad, base-sepolia"),
+            ("MONAD_CHAIN_ID", "10143"),
+            ("MONAD_RPC", "https://monad"),
+            ("MONAD_CONTRACT", "0x01"),
+            ("MONAD_START_BLOCK", "100"),
+            ("BASE_SEPOLIA_CHAIN_ID", "84532"),
+            ("BASE_SEPOLIA_RPC", "https://base"),
+            ("BASE_SEPOLIA_WS_RPC", "wss://base"),
+            ("BASE_SEPOLIA_CONTRACT", "0x02,0x03"),
+            ("BASE_SEPOLIA_START_BLOCK", "5"),
+            ("BASE_SEPOLIA_CONFIRMATIONS", "10"),
+        ]));
+        assert_eq!(networks.len(), 2);
+        assert_eq!(networks[1].name, "base-sepolia");
+        assert_eq!(networks[1].chain_id, 84532);
+        assert_eq!(networks[1].ws_rpc.as_deref(), Some("wss://base"));
+        assert_eq!(networks[1].confirmations, 10);
+        assert_eq!(networks[1].contracts, vec!["0x02".to_string(), "0x03".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_sui_deployments() {
+        assert!(parse_sui_deployments(vars(&[])).is_empty());
+
+        let legacy = parse_sui_deployments(vars(&[
+            ("SUI_CONTRACT", "0xA1"),
+            ("SUI_SHARES_TRADING_OBJECT_ID", "0xB1"),
+        ]));
+        assert_eq!(legacy, vec![SuiDeploymentConfig {
+            object_id: "0xb1".to_string(),
+            package_ids: vec!["0xa1".to_string()],
+        }]);
+
+        let deployments = parse_sui_deployments(vars(&[
+            ("SUI_DEPLOYMENTS", "0xb1:0xa1,0xa2; 0xb2:0xa3"),
+        ]));
+        assert_eq!(deployments.len(), 2);
+        assert_eq!(deployments[0].package_ids, vec!["0xa1".to_string(), "0xa2".to_string()]);
+        assert_eq!(deployments[1].object_id, "0xb2");
+    }
+
+    #[test]
+    fn test_parse_zklogin_jwk_sources() {
+        assert!(parse_zklogin_jwk_sources("").is_empty());
+        assert_eq!(
+            parse_zklogin_jwk_sources("https://accounts.google.com=/etc/jwks/google.json; https://id.twitch.tv/oauth2 = twitch.json"),
+            vec![
+                ZkLoginJwkSource { iss: "https://accounts.google.com".to_string(), path: "/etc/jwks/google.json".to_string() },
+                ZkLoginJwkSource { iss: "https://id.twitch.tv/oauth2".to_string(), path: "twitch.json".to_string() },
+            ]
+        );
+    }
+}
@@ -21,6 +21,7 @@ pub struct UserShares {
     pub subject: String,
     pub share_amount: BigDecimal,
     pub chain_type: String,
+    pub deployment: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -37,4 +38,115 @@ pub struct ChallengeResponse {
     pub success: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
-}
\ No newline at end of file
+}
+
+/// On-chain identity of a Trade event.
+/// Monad uses (transaction hash, log index), Sui uses (txDigest, eventSeq).
+#[derive(Clone, Debug)]
+pub struct TradeEventKey {
+    pub tx_hash: String,
+    pub log_index: i64,
+    pub block_number: Option<i64>,
+}
+
+/// Trade event fields archived verbatim next to the ledger entry
+#[derive(Clone, Debug)]
+pub struct TradeEventDetails {
+    /// Monad ethAmount / Sui price
+    pub price: BigDecimal,
+    pub protocol_fee: BigDecimal,
+    pub subject_fee: BigDecimal,
+    /// Subject supply emitted with the event
+    pub supply: BigDecimal,
+    pub timestamp_ms: Option<i64>,
+    /// Monad block hash
+    pub block_hash: Option<String>,
+    /// Sui checkpoint
+    pub checkpoint: Option<i64>,
+    /// Log or event as returned by the node
+    pub raw: serde_json::Value,
+}
+
+/// Persisted position of the Sui event query pagination
+#[derive(Clone, Debug)]
+pub struct SuiCursor {
+    /// Trade event type the query is filtered by
+    pub event_type: String,
+    pub tx_digest: String,
+    pub event_seq: i64,
+    pub checkpoint: Option<i64>,
+    pub timestamp_ms: Option<i64>,
+}
+
+/// Supply of a subject after its latest ingested Trade event
+#[derive(Clone, Debug)]
+pub struct SubjectSupply {
+    pub supply: BigDecimal,
+    pub last_tx_hash: String,
+    pub last_block_number: Option<i64>,
+    pub last_log_index: i64,
+    pub last_checkpoint: Option<i64>,
+}
+
+/// Block (Monad) or checkpoint (Sui) range queued for a re-scan after a supply gap
+#[derive(Clone, Debug)]
+pub struct SupplyGap {
+    pub id: i64,
+    pub chain_type: String,
+    pub deployment: String,
+    pub subject: String,
+    pub rescan_from: Option<i64>,
+    pub rescan_to: Option<i64>,
+}
+
+/// Trade event in the dead-letter queue
+#[derive(Clone, Debug)]
+pub struct FailedEvent {
+    pub id: i64,
+    pub chain_type: String,
+    pub tx_hash: String,
+    pub log_index: i64,
+    pub block_number: Option<i64>,
+    pub trader: String,
+    pub subject: String,
+    pub is_buy: bool,
+    pub share_amount: BigDecimal,
+    pub price: BigDecimal,
+    pub protocol_fee: BigDecimal,
+    pub subject_fee: BigDecimal,
+    pub supply: BigDecimal,
+    pub block_hash: Option<String>,
+    pub checkpoint: Option<i64>,
+    pub event_timestamp_ms: Option<i64>,
+    pub raw_event: serde_json::Value,
+    pub error: String,
+    pub attempts: i32,
+    pub status: String,
+    pub next_retry_at: time::OffsetDateTime,
+    pub deployment: String,
+}
+
+/// Nonce issued by `POST /challenge` for a Telegram user to sign with their wallet
+#[derive(Clone, Debug)]
+pub struct AuthChallenge {
+    pub nonce: String,
+    pub telegram_id: String,
+    pub chat_id: String,
+    pub chain_type: String,
+    pub message: String,
+    pub expires_at: time::OffsetDateTime,
+    pub used_at: Option<time::OffsetDateTime>,
+}
+
+/// Telegram permission change waiting in the outbox
+#[derive(Clone, Debug)]
+pub struct TelegramOutboxEntry {
+    pub id: i64,
+    pub action: String,
+    pub agent_name: String,
+    /// Current token of the bot, `None` when it was removed from `telegram_bots`
+    pub bot_token: Option<String>,
+    pub chat_group_id: String,
+    pub telegram_id: String,
+    pub attempts: i32,
+}
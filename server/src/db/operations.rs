@@ -1,8 +1,8 @@
-use sqlx::{PgPool, types::BigDecimal};
+use sqlx::{PgConnection, PgPool, types::BigDecimal};
 use std::str::FromStr;
 use ethers::prelude::*;
 use anyhow;
-use crate::db::models::UserShares;
+use crate::db::models::{AuthChallenge, FailedEvent, SubjectSupply, SuiCursor, SupplyGap, TelegramOutboxEntry, TradeEventDetails, TradeEventKey, UserShares};
 
 // Get the last synchronized block number
 pub async fn get_last_synced_block(pool: &PgPool, start_block: u64, chain_type: &str) -> Result<u64, sqlx::Error> {
@@ -30,68 +30,82 @@ pub async fn get_last_synced_block(pool: &PgPool, start_block: u64, chain_type:
     }
 }
 
-// Get the last synchronized block number with metadata
-pub async fn get_last_synced_block_with_metadata(
-    pool: &PgPool, 
-    start_block: u64, 
-    chain_type: &str
-) -> Result<(u64, Option<String>), sqlx::Error> {
-    let record = sqlx::query!(
-        "SELECT last_synced_block, metadata FROM sync_status WHERE chain_type = $1 ORDER BY id DESC LIMIT 1",
-        chain_type
-    )
-    .fetch_optional(pool)
-    .await?;
-    
-    match record {
-        Some(row) => Ok((row.last_synced_block as u64, row.metadata)),
-        None => {
-            // If no record exists, insert the initial block number
-            sqlx::query!(
-                "INSERT INTO sync_status (last_synced_block, chain_type) VALUES ($1, $2)",
-                start_block as i64,
-                chain_type
-            )
-            .execute(pool)
-            .await?;
-            
-            Ok((start_block, None))
-        }
-    }
-}
-
 // Update the last synchronized block number
-pub async fn update_last_synced_block(pool: &PgPool, block_number: u64, chain_type: &str) -> Result<(), sqlx::Error> {
+pub async fn update_last_synced_block(conn: &mut PgConnection, block_number: u64, chain_type: &str) -> Result<(), sqlx::Error> {
     sqlx::query!(
         "UPDATE sync_status SET last_synced_block = $1 WHERE chain_type = $2 AND id = (SELECT id FROM sync_status WHERE chain_type = $2 ORDER BY id DESC LIMIT 1)",
         block_number as i64,
         chain_type
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
     
     Ok(())
 }
 
+// Record a trade event in the ledger, returns false if it was already ingested
+pub async fn record_trade_event(
+    conn: &mut PgConnection,
+    key: &TradeEventKey,
+    trader: &str,
+    subject: &str,
+    is_buy: bool,
+    share_amount: &BigDecimal,
+    details: &TradeEventDetails,
+    chain_type: &str,
+    deployment: &str
+) -> Result<bool, sqlx::Error> {
+    let inserted = sqlx::query!(
+        "INSERT INTO trade_events (chain_type, tx_hash, log_index, block_number, trader, subject, is_buy, share_amount,
+            price, protocol_fee, subject_fee, supply, block_hash, checkpoint, event_timestamp_ms, raw_event, deployment)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)
+        ON CONFLICT (chain_type, tx_hash, log_index) DO NOTHING
+        RETURNING id",
+        chain_type,
+        key.tx_hash,
+        key.log_index,
+        key.block_number,
+        trader,
+        subject,
+        is_buy,
+        share_amount,
+        details.price,
+        details.protocol_fee,
+        details.subject_fee,
+        details.supply,
+        details.block_hash,
+        details.checkpoint,
+        details.timestamp_ms,
+        details.raw,
+        deployment
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(inserted.is_some())
+}
+
 // Process buy trade
 pub async fn process_buy_trade(
-    pool: &PgPool, 
+    conn: &mut PgConnection, 
     trader: String, 
     subject: String, 
     share_amount: BigDecimal,
-    chain_type: &str
+    chain_type: &str,
+    deployment: &str
 ) -> anyhow::Result<()> {
     sqlx::query!(
-        "INSERT INTO trades (trader, subject, share_amount, chain_type) 
-        VALUES ($1, $2, $3, $4) 
-        ON CONFLICT (trader, subject, chain_type) 
+        "INSERT INTO trades (trader, subject, share_amount, chain_type, deployment) 
+        VALUES ($1, $2, $3, $4, $5) 
+        ON CONFLICT (trader, subject, chain_type, deployment) 
         DO UPDATE SET share_amount = trades.share_amount + $3",
         trader,
         subject,
         share_amount,
-        chain_type
+        chain_type,
+        deployment
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
     
     Ok(())
@@ -99,22 +113,24 @@ pub async fn process_buy_trade(
 
 // Process sell trade
 pub async fn process_sell_trade(
-    pool: &PgPool, 
+    conn: &mut PgConnection, 
     trader: String, 
     subject: String, 
     share_amount: BigDecimal,
-    chain_type: &str
+    chain_type: &str,
+    deployment: &str
 ) -> anyhow::Result<(bool, Option<String>)> {
     let ret = sqlx::query!(
         "UPDATE trades SET share_amount = share_amount - $1 
-        WHERE trader = $2 AND subject = $3 AND chain_type = $4
+        WHERE trader = $2 AND subject = $3 AND chain_type = $4 AND deployment = $5
         RETURNING share_amount",
         share_amount,
         trader,
         subject,
-        chain_type
+        chain_type,
+        deployment
     )
-    .fetch_optional(pool)
+    .fetch_optional(&mut *conn)
     .await?;
     
     match ret {
@@ -127,7 +143,7 @@ pub async fn process_sell_trade(
                     trader,
                     chain_type
                 )
-                .fetch_optional(pool)
+                .fetch_optional(&mut *conn)
                 .await?;
                 
                 if let Some(user_record) = telegram_id {
@@ -137,7 +153,7 @@ pub async fn process_sell_trade(
             Ok((false, None))
         },
         None => {
-            println!("Trade record not found: trader={}, subject={}, chain={}", trader, subject, chain_type);
+            println!("Trade record not found: trader={}, subject={}, chain={}, deployment={}", trader, subject, chain_type, deployment);
             Ok((false, None))
         }
     }
@@ -148,13 +164,15 @@ pub async fn get_user_subject_shares(
     pool: &PgPool,
     trader: &str,
     subject: &str,
-    chain_type: &str
+    chain_type: &str,
+    deployment: &str
 ) -> Result<BigDecimal, sqlx::Error> {
     let record = sqlx::query!(
-        "SELECT share_amount FROM trades WHERE trader = $1 AND subject = $2 AND chain_type = $3",
+        "SELECT share_amount FROM trades WHERE trader = $1 AND subject = $2 AND chain_type = $3 AND deployment = $4",
         trader,
         subject,
-        chain_type
+        chain_type,
+        deployment
     )
     .fetch_optional(pool)
     .await?;
@@ -172,7 +190,7 @@ pub async fn get_user_shares(
 ) -> Result<Vec<UserShares>, sqlx::Error> {
     let rows = sqlx::query_as!(
         UserShares,
-        "SELECT trader, subject, share_amount, chain_type FROM trades WHERE trader = $1 AND chain_type = $2",
+        "SELECT trader, subject, share_amount, chain_type, deployment FROM trades WHERE trader = $1 AND chain_type = $2",
         trader,
         chain_type
     )
@@ -182,25 +200,915 @@ pub async fn get_user_shares(
     Ok(rows)
 }
 
-// Update last synchronized block info with metadata
-pub async fn update_last_synced_block_with_metadata(
-    pool: &PgPool, 
-    block_number: u64, 
-    metadata: String,
-    chain_type: &str
+// Get the persisted Sui event cursor
+pub async fn get_sui_cursor(pool: &PgPool, chain_type: &str, event_type: &str) -> Result<Option<SuiCursor>, sqlx::Error> {
+    let cursor = sqlx::query_as!(
+        SuiCursor,
+        "SELECT event_type, tx_digest, event_seq, checkpoint, timestamp_ms FROM sui_event_cursors
+        WHERE chain_type = $1 AND event_type = $2",
+        chain_type,
+        event_type
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(cursor)
+}
+
+// Save the Sui event cursor
+pub async fn save_sui_cursor(conn: &mut PgConnection, cursor: &SuiCursor, chain_type: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO sui_event_cursors (chain_type, event_type, tx_digest, event_seq, checkpoint, timestamp_ms)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (chain_type, event_type)
+        DO UPDATE SET tx_digest = $3, event_seq = $4, checkpoint = $5, timestamp_ms = $6",
+        chain_type,
+        cursor.event_type,
+        cursor.tx_digest,
+        cursor.event_seq,
+        cursor.checkpoint,
+        cursor.timestamp_ms
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+// Save the hash of an indexed block
+pub async fn save_indexed_block(
+    conn: &mut PgConnection,
+    chain_type: &str,
+    block_number: u64,
+    block_hash: &str,
+    parent_hash: &str
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
-        "UPDATE sync_status 
-         SET last_synced_block = $1, metadata = $2 
-         WHERE chain_type = $3 AND id = (
-             SELECT id FROM sync_status WHERE chain_type = $3 ORDER BY id DESC LIMIT 1
-         )",
+        "INSERT INTO indexed_blocks (chain_type, block_number, block_hash, parent_hash)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (chain_type, block_number)
+        DO UPDATE SET block_hash = $3, parent_hash = $4",
+        chain_type,
         block_number as i64,
-        metadata,
+        block_hash,
+        parent_hash
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+// Drop block hashes older than the given block number
+pub async fn prune_indexed_blocks(conn: &mut PgConnection, chain_type: &str, before_block: u64) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM indexed_blocks WHERE chain_type = $1 AND block_number < $2",
+        chain_type,
+        before_block as i64
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+// Get the hash recorded for an indexed block
+pub async fn get_indexed_block_hash(pool: &PgPool, chain_type: &str, block_number: u64) -> Result<Option<String>, sqlx::Error> {
+    let record = sqlx::query!(
+        "SELECT block_hash FROM indexed_blocks WHERE chain_type = $1 AND block_number = $2",
+        chain_type,
+        block_number as i64
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|row| row.block_hash))
+}
+
+// Get indexed blocks up to the given block number, newest first
+pub async fn get_indexed_blocks_desc(pool: &PgPool, chain_type: &str, up_to_block: u64) -> Result<Vec<(u64, String)>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT block_number, block_hash FROM indexed_blocks
+        WHERE chain_type = $1 AND block_number <= $2
+        ORDER BY block_number DESC",
+        chain_type,
+        up_to_block as i64
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| (row.block_number as u64, row.block_hash)).collect())
+}
+
+// Revert balance changes of trades from orphaned blocks and forget those blocks,
+// returns the number of trade events rolled back
+pub async fn rollback_trades_after_block(conn: &mut PgConnection, chain_type: &str, block_number: u64) -> Result<u64, sqlx::Error> {
+    let orphaned = sqlx::query!(
+        "SELECT COUNT(*) AS count FROM trade_events WHERE chain_type = $1 AND block_number > $2",
+        chain_type,
+        block_number as i64
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    // Undo each orphaned trade: buys are subtracted again, sells are added back
+    sqlx::query!(
+        "WITH orphaned AS (
+            DELETE FROM trade_events WHERE chain_type = $1 AND block_number > $2
+            RETURNING trader, subject, deployment, CASE WHEN is_buy THEN -share_amount ELSE share_amount END AS delta
+        ), deltas AS (
+            SELECT trader, subject, deployment, SUM(delta) AS delta FROM orphaned GROUP BY trader, subject, deployment
+        )
+        UPDATE trades SET share_amount = trades.share_amount + deltas.delta
+        FROM deltas
+        WHERE trades.trader = deltas.trader AND trades.subject = deltas.subject
+            AND trades.chain_type = $1 AND trades.deployment = deltas.deployment",
+        chain_type,
+        block_number as i64
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM indexed_blocks WHERE chain_type = $1 AND block_number > $2",
+        chain_type,
+        block_number as i64
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM failed_events WHERE chain_type = $1 AND block_number > $2 AND status <> 'resolved'",
+        chain_type,
+        block_number as i64
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    refresh_subject_supplies(conn, chain_type, block_number as i64 + 1).await?;
+
+    Ok(orphaned.count.unwrap_or(0) as u64)
+}
+
+// Get the identities of ledger events within a block range
+pub async fn get_trade_event_keys_in_blocks(
+    conn: &mut PgConnection,
+    chain_type: &str,
+    from_block: u64,
+    to_block: u64
+) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT tx_hash, log_index FROM trade_events
+        WHERE chain_type = $1 AND block_number >= $2 AND block_number <= $3",
+        chain_type,
+        from_block as i64,
+        to_block as i64
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| (row.tx_hash, row.log_index)).collect())
+}
+
+// Remove a single event from the ledger and revert its balance change,
+// returns false if the event was never ingested
+pub async fn revert_trade_event(
+    conn: &mut PgConnection,
+    chain_type: &str,
+    tx_hash: &str,
+    log_index: i64
+) -> Result<bool, sqlx::Error> {
+    let reverted = sqlx::query!(
+        "DELETE FROM trade_events WHERE chain_type = $1 AND tx_hash = $2 AND log_index = $3
+        RETURNING trader, subject, is_buy, share_amount, block_number, deployment",
+        chain_type,
+        tx_hash,
+        log_index
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some(event) = reverted else {
+        return Ok(false);
+    };
+
+    let delta = if event.is_buy { -event.share_amount } else { event.share_amount };
+    sqlx::query!(
+        "UPDATE trades SET share_amount = share_amount + $1
+        WHERE trader = $2 AND subject = $3 AND chain_type = $4 AND deployment = $5",
+        delta,
+        event.trader,
+        event.subject,
+        chain_type,
+        event.deployment
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    if let Some(block_number) = event.block_number {
+        refresh_subject_supplies(conn, chain_type, block_number).await?;
+    }
+
+    Ok(true)
+}
+
+// Block writes to the ledger and the holdings projection until the transaction ends
+pub async fn lock_trade_projection(conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+    sqlx::query!("LOCK TABLE trade_events, trades IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+// Replay the ledger of a chain into trades_shadow, returns the number of holdings rebuilt.
+// Balances corrected by the reconciler are replayed from share_discrepancies on top of the events.
+// Holdings recorded before the ledger existed have no events, they are carried over as they are.
+pub async fn rebuild_trades_shadow(conn: &mut PgConnection, chain_type: &str) -> Result<u64, sqlx::Error> {
+    sqlx::query!("DELETE FROM trades_shadow WHERE chain_type = $1", chain_type)
+        .execute(&mut *conn)
+        .await?;
+
+    let rebuilt = sqlx::query!(
+        "INSERT INTO trades_shadow (trader, subject, share_amount, chain_type, deployment)
+        SELECT trader, subject, SUM(CASE WHEN is_buy THEN share_amount ELSE -share_amount END), chain_type, deployment
+        FROM trade_events
+        WHERE chain_type = $1
+        GROUP BY trader, subject, chain_type, deployment",
+        chain_type
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE trades_shadow SET share_amount = trades_shadow.share_amount + corrections.amount
+        FROM (
+            SELECT trader, subject, chain_type, deployment, SUM(onchain_amount - indexed_amount) AS amount
+            FROM share_discrepancies
+            WHERE chain_type = $1 AND corrected
+            GROUP BY trader, subject, chain_type, deployment
+        ) corrections
+        WHERE trades_shadow.trader = corrections.trader
+            AND trades_shadow.subject = corrections.subject
+            AND trades_shadow.chain_type = corrections.chain_type
+            AND trades_shadow.deployment = corrections.deployment",
+        chain_type
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let carried_over = sqlx::query!(
+        "INSERT INTO trades_shadow (trader, subject, share_amount, chain_type, deployment)
+        SELECT trader, subject, share_amount, chain_type, deployment FROM trades
+        WHERE chain_type = $1 AND NOT EXISTS (
+            SELECT 1 FROM trade_events
+            WHERE trade_events.trader = trades.trader
+                AND trade_events.subject = trades.subject
+                AND trade_events.chain_type = trades.chain_type
+                AND trade_events.deployment = trades.deployment
+        )",
+        chain_type
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(rebuilt.rows_affected() + carried_over.rows_affected())
+}
+
+// Count rebuilt holdings with a negative balance, which means the ledger is missing events
+pub async fn count_negative_shadow_holdings(conn: &mut PgConnection, chain_type: &str) -> Result<i64, sqlx::Error> {
+    let record = sqlx::query!(
+        "SELECT COUNT(*) AS count FROM trades_shadow WHERE chain_type = $1 AND share_amount < 0",
+        chain_type
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(record.count.unwrap_or(0))
+}
+
+// Count holdings whose rebuilt balance differs from the current projection
+pub async fn count_shadow_differences(conn: &mut PgConnection, chain_type: &str) -> Result<i64, sqlx::Error> {
+    let record = sqlx::query!(
+        "SELECT COUNT(*) AS count
+        FROM (SELECT * FROM trades WHERE chain_type = $1) current
+        FULL OUTER JOIN (SELECT * FROM trades_shadow WHERE chain_type = $1) shadow
+            ON current.trader = shadow.trader AND current.subject = shadow.subject AND current.deployment = shadow.deployment
+        WHERE COALESCE(current.share_amount, 0) <> COALESCE(shadow.share_amount, 0)",
+        chain_type
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(record.count.unwrap_or(0))
+}
+
+// Replace the holdings of a chain with the verified shadow rows
+pub async fn swap_in_trades_shadow(conn: &mut PgConnection, chain_type: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO trades (trader, subject, share_amount, chain_type, deployment)
+        SELECT trader, subject, share_amount, chain_type, deployment FROM trades_shadow WHERE chain_type = $1
+        ON CONFLICT (trader, subject, chain_type, deployment)
+        DO UPDATE SET share_amount = EXCLUDED.share_amount
+        WHERE trades.share_amount <> EXCLUDED.share_amount",
+        chain_type
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM trades WHERE chain_type = $1 AND NOT EXISTS (
+            SELECT 1 FROM trades_shadow
+            WHERE trades_shadow.trader = trades.trader
+                AND trades_shadow.subject = trades.subject
+                AND trades_shadow.chain_type = trades.chain_type
+                AND trades_shadow.deployment = trades.deployment
+        )",
+        chain_type
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!("DELETE FROM trades_shadow WHERE chain_type = $1", chain_type)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+// Pick random holdings to compare against the chain
+pub async fn sample_trades(pool: &PgPool, limit: i64) -> Result<Vec<UserShares>, sqlx::Error> {
+    sqlx::query_as!(
+        UserShares,
+        "SELECT trader, subject, share_amount, chain_type, deployment FROM trades ORDER BY random() LIMIT $1",
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Page through all holdings ordered by (chain_type, deployment, trader, subject), starting after `after`
+pub async fn get_trades_page(pool: &PgPool, after: Option<&UserShares>, limit: i64) -> Result<Vec<UserShares>, sqlx::Error> {
+    sqlx::query_as!(
+        UserShares,
+        "SELECT trader, subject, share_amount, chain_type, deployment FROM trades
+        WHERE $1::VARCHAR IS NULL OR (chain_type, deployment, trader, subject) > ($1, $2, $3, $4)
+        ORDER BY chain_type, deployment, trader, subject
+        LIMIT $5",
+        after.map(|row| row.chain_type.as_str()),
+        after.map(|row| row.deployment.as_str()),
+        after.map(|row| row.trader.as_str()),
+        after.map(|row| row.subject.as_str()),
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Record a holding whose indexed balance differs from the chain
+pub async fn record_share_discrepancy(
+    conn: &mut PgConnection,
+    holding: &UserShares,
+    onchain_amount: &BigDecimal,
+    corrected: bool
+) -> Result<i64, sqlx::Error> {
+    let record = sqlx::query!(
+        "INSERT INTO share_discrepancies (chain_type, trader, subject, indexed_amount, onchain_amount, corrected, deployment)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id",
+        holding.chain_type,
+        holding.trader,
+        holding.subject,
+        holding.share_amount,
+        onchain_amount,
+        corrected,
+        holding.deployment
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(record.id)
+}
+
+// Overwrite an indexed balance with the on-chain one, unless the indexer changed it meanwhile
+pub async fn correct_trade_balance(
+    conn: &mut PgConnection,
+    holding: &UserShares,
+    onchain_amount: &BigDecimal
+) -> Result<bool, sqlx::Error> {
+    let corrected = sqlx::query!(
+        "UPDATE trades SET share_amount = $1
+        WHERE trader = $2 AND subject = $3 AND chain_type = $4 AND deployment = $5 AND share_amount = $6",
+        onchain_amount,
+        holding.trader,
+        holding.subject,
+        holding.chain_type,
+        holding.deployment,
+        holding.share_amount
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(corrected.rows_affected() > 0)
+}
+
+// Get the supply projected from the latest ingested event of a subject
+pub async fn get_subject_supply(
+    conn: &mut PgConnection,
+    chain_type: &str,
+    deployment: &str,
+    subject: &str
+) -> Result<Option<SubjectSupply>, sqlx::Error> {
+    sqlx::query_as!(
+        SubjectSupply,
+        "SELECT supply, last_tx_hash, last_block_number, last_log_index, last_checkpoint FROM subject_supply
+        WHERE chain_type = $1 AND deployment = $2 AND subject = $3",
+        chain_type,
+        deployment,
+        subject
+    )
+    .fetch_optional(&mut *conn)
+    .await
+}
+
+// Advance the supply projection of a subject to an ingested event
+pub async fn save_subject_supply(
+    conn: &mut PgConnection,
+    chain_type: &str,
+    deployment: &str,
+    subject: &str,
+    supply: &BigDecimal,
+    key: &TradeEventKey,
+    checkpoint: Option<i64>
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO subject_supply (chain_type, deployment, subject, supply, last_tx_hash, last_log_index, last_block_number, last_checkpoint)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (chain_type, deployment, subject)
+        DO UPDATE SET supply = $4, last_tx_hash = $5, last_log_index = $6, last_block_number = $7, last_checkpoint = $8",
+        chain_type,
+        deployment,
+        subject,
+        supply,
+        key.tx_hash,
+        key.log_index,
+        key.block_number,
+        checkpoint
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+// Reset the supply of subjects whose latest event is at or after `from_block` to the
+// latest event left in the ledger, after events of those blocks were reverted
+pub async fn refresh_subject_supplies(conn: &mut PgConnection, chain_type: &str, from_block: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "WITH latest AS (
+            SELECT DISTINCT ON (deployment, subject) deployment, subject, supply, tx_hash, log_index, block_number
+            FROM trade_events
+            WHERE chain_type = $1 AND supply IS NOT NULL AND (deployment, subject) IN (
+                SELECT deployment, subject FROM subject_supply WHERE chain_type = $1 AND last_block_number >= $2
+            )
+            ORDER BY deployment, subject, block_number DESC, log_index DESC
+        )
+        UPDATE subject_supply
+        SET supply = latest.supply, last_tx_hash = latest.tx_hash, last_log_index = latest.log_index, last_block_number = latest.block_number
+        FROM latest
+        WHERE subject_supply.chain_type = $1 AND subject_supply.deployment = latest.deployment AND subject_supply.subject = latest.subject",
+        chain_type,
+        from_block
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    // Subjects without any event left start over with their next event
+    sqlx::query!(
+        "DELETE FROM subject_supply WHERE chain_type = $1 AND last_block_number >= $2",
+        chain_type,
+        from_block
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+// Record an event whose supply does not continue the previous supply of its subject
+pub async fn record_supply_gap(
+    conn: &mut PgConnection,
+    chain_type: &str,
+    deployment: &str,
+    subject: &str,
+    expected_supply: &BigDecimal,
+    event_supply: &BigDecimal,
+    key: &TradeEventKey,
+    rescan_range: Option<(i64, i64)>
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO supply_gaps (chain_type, deployment, subject, expected_supply, event_supply, tx_hash, log_index, rescan_from, rescan_to)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        chain_type,
+        deployment,
+        subject,
+        expected_supply,
+        event_supply,
+        key.tx_hash,
+        key.log_index,
+        rescan_range.map(|(from, _)| from),
+        rescan_range.map(|(_, to)| to)
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+// Get supply gaps waiting for a re-scan, oldest first
+pub async fn get_pending_supply_gaps(pool: &PgPool, chain_types: &[String], limit: i64) -> Result<Vec<SupplyGap>, sqlx::Error> {
+    sqlx::query_as!(
+        SupplyGap,
+        "SELECT id, chain_type, deployment, subject, rescan_from, rescan_to FROM supply_gaps
+        WHERE status = 'pending' AND chain_type = ANY($1)
+        ORDER BY id
+        LIMIT $2",
+        chain_types,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Update the re-scan status of a supply gap
+pub async fn update_supply_gap_status(pool: &PgPool, id: i64, status: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE supply_gaps SET status = $1 WHERE id = $2",
+        status,
+        id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Add a Trade event that failed to apply to the dead-letter queue, or count another
+// failed attempt with exponential backoff. Out of attempts, the event is marked dead.
+pub async fn record_failed_event(
+    conn: &mut PgConnection,
+    key: &TradeEventKey,
+    trader: &str,
+    subject: &str,
+    is_buy: bool,
+    share_amount: &BigDecimal,
+    details: &TradeEventDetails,
+    error: &str,
+    max_attempts: i32,
+    chain_type: &str,
+    deployment: &str
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO failed_events (chain_type, tx_hash, log_index, block_number, trader, subject, is_buy, share_amount,
+            price, protocol_fee, subject_fee, supply, block_hash, checkpoint, event_timestamp_ms, raw_event,
+            error, deployment, next_retry_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $19, NOW() + INTERVAL '30 seconds')
+        ON CONFLICT (chain_type, tx_hash, log_index)
+        DO UPDATE SET
+            error = EXCLUDED.error,
+            attempts = failed_events.attempts + 1,
+            status = CASE WHEN failed_events.attempts + 1 >= $18 THEN 'dead' ELSE 'pending' END,
+            next_retry_at = NOW() + LEAST(INTERVAL '30 seconds' * POWER(2, failed_events.attempts), INTERVAL '1 hour')",
+        chain_type,
+        key.tx_hash,
+        key.log_index,
+        key.block_number,
+        trader,
+        subject,
+        is_buy,
+        share_amount,
+        details.price,
+        details.protocol_fee,
+        details.subject_fee,
+        details.supply,
+        details.block_hash,
+        details.checkpoint,
+        details.timestamp_ms,
+        details.raw,
+        error,
+        max_attempts,
+        deployment
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+// Get dead-lettered events whose next retry is due
+pub async fn get_due_failed_events(pool: &PgPool, limit: i64) -> Result<Vec<FailedEvent>, sqlx::Error> {
+    sqlx::query_as!(
+        FailedEvent,
+        "SELECT id, chain_type, tx_hash, log_index, block_number, trader, subject, is_buy, share_amount,
+            price, protocol_fee, subject_fee, supply, block_hash, checkpoint, event_timestamp_ms, raw_event,
+            error, attempts, status, next_retry_at, deployment
+        FROM failed_events
+        WHERE status = 'pending' AND next_retry_at <= NOW()
+        ORDER BY next_retry_at
+        LIMIT $1",
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// List dead-lettered events, newest first, optionally filtered by status and chain
+pub async fn list_failed_events(
+    pool: &PgPool,
+    status: Option<&str>,
+    chain_type: Option<&str>,
+    limit: i64,
+    offset: i64
+) -> Result<Vec<FailedEvent>, sqlx::Error> {
+    sqlx::query_as!(
+        FailedEvent,
+        "SELECT id, chain_type, tx_hash, log_index, block_number, trader, subject, is_buy, share_amount,
+            price, protocol_fee, subject_fee, supply, block_hash, checkpoint, event_timestamp_ms, raw_event,
+            error, attempts, status, next_retry_at, deployment
+        FROM failed_events
+        WHERE ($1::VARCHAR IS NULL OR status = $1) AND ($2::VARCHAR IS NULL OR chain_type = $2)
+        ORDER BY id DESC
+        LIMIT $3 OFFSET $4",
+        status,
+        chain_type,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Make a dead-lettered event due for retry again with a fresh attempt budget
+pub async fn reset_failed_event(pool: &PgPool, id: i64) -> Result<Option<FailedEvent>, sqlx::Error> {
+    sqlx::query_as!(
+        FailedEvent,
+        "UPDATE failed_events SET status = 'pending', attempts = 0, next_retry_at = NOW()
+        WHERE id = $1 AND status <> 'resolved'
+        RETURNING id, chain_type, tx_hash, log_index, block_number, trader, subject, is_buy, share_amount,
+            price, protocol_fee, subject_fee, supply, block_hash, checkpoint, event_timestamp_ms, raw_event,
+            error, attempts, status, next_retry_at, deployment",
+        id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+// Mark a dead-lettered event as applied
+pub async fn resolve_failed_event(pool: &PgPool, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE failed_events SET status = 'resolved' WHERE id = $1",
+        id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Check whether a Trade event is in the ledger
+pub async fn is_trade_event_recorded(pool: &PgPool, chain_type: &str, tx_hash: &str, log_index: i64) -> Result<bool, sqlx::Error> {
+    let record = sqlx::query!(
+        "SELECT EXISTS(SELECT 1 FROM trade_events WHERE chain_type = $1 AND tx_hash = $2 AND log_index = $3) AS recorded",
+        chain_type,
+        tx_hash,
+        log_index
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(record.recorded.unwrap_or(false))
+}
+
+// Drop unresolved dead-lettered events of a block range that are not part of the canonical chain
+pub async fn discard_orphaned_failed_events(
+    conn: &mut PgConnection,
+    chain_type: &str,
+    from_block: u64,
+    to_block: u64,
+    canonical_tx_hashes: &[String],
+    canonical_log_indexes: &[i64]
+) -> Result<u64, sqlx::Error> {
+    let discarded = sqlx::query!(
+        "DELETE FROM failed_events
+        WHERE chain_type = $1 AND block_number >= $2 AND block_number <= $3 AND status <> 'resolved'
+            AND (tx_hash, log_index) NOT IN (SELECT * FROM UNNEST($4::VARCHAR[], $5::BIGINT[]))",
+        chain_type,
+        from_block as i64,
+        to_block as i64,
+        canonical_tx_hashes,
+        canonical_log_indexes
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(discarded.rows_affected())
+}
+
+// Drop an unresolved dead-lettered event removed from the chain by a reorg
+pub async fn discard_failed_event(conn: &mut PgConnection, chain_type: &str, tx_hash: &str, log_index: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM failed_events WHERE chain_type = $1 AND tx_hash = $2 AND log_index = $3 AND status <> 'resolved'",
+        chain_type,
+        tx_hash,
+        log_index
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+// Queue a Telegram permission change. Pending changes for the same member of the same
+// chat are superseded, only the latest intent is executed. A cause is only queued once.
+pub async fn enqueue_telegram_action(
+    conn: &mut PgConnection,
+    dedup_key: &str,
+    action: &str,
+    agent_name: &str,
+    chat_group_id: &str,
+    telegram_id: &str,
+    chain_type: &str
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE telegram_outbox SET status = 'superseded'
+        WHERE status = 'pending' AND agent_name = $1 AND chat_group_id = $2 AND telegram_id = $3 AND dedup_key <> $4",
+        agent_name,
+        chat_group_id,
+        telegram_id,
+        dedup_key
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO telegram_outbox (dedup_key, action, agent_name, chat_group_id, telegram_id, chain_type)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (dedup_key) DO NOTHING",
+        dedup_key,
+        action,
+        agent_name,
+        chat_group_id,
+        telegram_id,
         chain_type
     )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+// Get queued Telegram permission changes that are due, in the order they were queued,
+// with the current token of their bot
+pub async fn get_due_telegram_actions(pool: &PgPool, limit: i64) -> Result<Vec<TelegramOutboxEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        TelegramOutboxEntry,
+        r#"SELECT outbox.id, outbox.action, outbox.agent_name, bots.bot_token AS "bot_token?",
+            outbox.chat_group_id, outbox.telegram_id, outbox.attempts
+        FROM telegram_outbox outbox
+        LEFT JOIN telegram_bots bots ON bots.agent_name = outbox.agent_name
+        WHERE outbox.status = 'pending' AND outbox.next_attempt_at <= NOW()
+        ORDER BY outbox.id
+        LIMIT $1"#,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Mark a queued Telegram permission change as executed
+pub async fn mark_telegram_action_sent(pool: &PgPool, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE telegram_outbox SET status = 'sent', attempts = attempts + 1, last_error = NULL WHERE id = $1",
+        id
+    )
     .execute(pool)
     .await?;
-    
+
     Ok(())
-}
\ No newline at end of file
+}
+
+// Count a failed attempt with exponential backoff, out of attempts the change is marked dead
+pub async fn mark_telegram_action_failed(pool: &PgPool, id: i64, error: &str, max_attempts: i32) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE telegram_outbox SET
+            attempts = attempts + 1,
+            last_error = $2,
+            status = CASE WHEN status = 'pending' AND attempts + 1 >= $3 THEN 'dead' ELSE status END,
+            next_attempt_at = NOW() + LEAST(INTERVAL '5 seconds' * POWER(2, attempts), INTERVAL '1 hour')
+        WHERE id = $1",
+        id,
+        error,
+        max_attempts
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Assign rows written before deployments were tracked to the first deployment of their chain
+pub async fn assign_legacy_deployment(pool: &PgPool, chain_type: &str, deployment: &str) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!("UPDATE trade_events SET deployment = $2 WHERE chain_type = $1 AND deployment = ''", chain_type, deployment)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("UPDATE trades SET deployment = $2 WHERE chain_type = $1 AND deployment = ''", chain_type, deployment)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("UPDATE subject_supply SET deployment = $2 WHERE chain_type = $1 AND deployment = ''", chain_type, deployment)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("UPDATE failed_events SET deployment = $2 WHERE chain_type = $1 AND deployment = ''", chain_type, deployment)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("UPDATE supply_gaps SET deployment = $2 WHERE chain_type = $1 AND deployment = ''", chain_type, deployment)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("UPDATE share_discrepancies SET deployment = $2 WHERE chain_type = $1 AND deployment = ''", chain_type, deployment)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("UPDATE telegram_bots SET deployment = $2 WHERE chain_type = $1 AND deployment = ''", chain_type, deployment)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+// Assign the event cursor saved before cursors were kept per event type to `event_type`
+pub async fn assign_legacy_sui_cursor(pool: &PgPool, chain_type: &str, event_type: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE sui_event_cursors SET event_type = $2
+        WHERE chain_type = $1 AND event_type = ''
+            AND NOT EXISTS (SELECT 1 FROM sui_event_cursors WHERE chain_type = $1 AND event_type = $2)",
+        chain_type,
+        event_type
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Store a newly issued challenge
+pub async fn create_auth_challenge(pool: &PgPool, challenge: &AuthChallenge) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO auth_challenges (nonce, telegram_id, chat_id, chain_type, message, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)",
+        challenge.nonce,
+        challenge.telegram_id,
+        challenge.chat_id,
+        challenge.chain_type,
+        challenge.message,
+        challenge.expires_at
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Get a challenge by its nonce, whether it is still usable or not
+pub async fn get_auth_challenge(pool: &PgPool, nonce: &str) -> Result<Option<AuthChallenge>, sqlx::Error> {
+    sqlx::query_as!(
+        AuthChallenge,
+        "SELECT nonce, telegram_id, chat_id, chain_type, message, expires_at, used_at FROM auth_challenges WHERE nonce = $1",
+        nonce
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+// Mark a challenge as used, returns false when it was already used or has expired
+pub async fn consume_auth_challenge(pool: &PgPool, nonce: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE auth_challenges SET used_at = NOW() WHERE nonce = $1 AND used_at IS NULL AND expires_at > NOW()",
+        nonce
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+// Delete challenges that expired more than a day ago
+pub async fn delete_stale_auth_challenges(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM auth_challenges WHERE expires_at < NOW() - INTERVAL '1 day'"
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
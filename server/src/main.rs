@@ -1,5 +1,8 @@
 mod block_chain;
+mod config;
 mod db;
+mod reconciler;
+mod reindex;
 mod routes;
 
 use std::env;
@@ -10,8 +13,14 @@ use std::sync::Arc;
 use std::time::Duration;
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use crate::routes::signature::handle_verify;
+use crate::routes::challenge::issue_challenge;
 use crate::routes::agent::{handle_add_tg_bot,get_agents,get_agent_by_name,get_agent_detail};
 use crate::routes::user::get_user_shares_handler;
+use crate::reconciler::Reconciler;
+use crate::block_chain::supply::run_supply_rescans;
+use crate::block_chain::dead_letter::run_failed_event_retries;
+use crate::block_chain::outbox::run_outbox_worker;
+use crate::routes::admin::{get_failed_events, replay_failed_event};
 const ABI: &str = r#"[	{
 		"inputs": [
 			{
@@ -41,17 +50,41 @@ const ABI: &str = r#"[	{
 struct AppConfig {
     telegram_bot_token: String,
     telegram_group_id: String,
-    shares_contract: String,
-    chain_rpc: String,
     database_url: String,
-    start_block: u64,
+    // Every indexer, worker and route shares this pool
+    database_max_connections: u32,
+    // EVM networks indexed under their name
+    evm_networks: Vec<EvmNetworkConfig>,
+    // Chain types to run, every configured chain when unset
+    enabled_chains: Option<Vec<String>>,
+    chain_min_block_range: u64,
+    chain_max_block_range: u64,
+    rpc_requests_per_second: u32,
+    // Reconciliation of indexed holdings against the chain
+    reconcile_interval_secs: u64,
+    reconcile_sample_size: i64,
+    reconcile_auto_correct: bool,
+    admin_token: Option<String>,
+    // Lifetime of the nonces issued by POST /challenge
+    challenge_ttl_secs: u64,
+    // Site EVM wallets sign in to, checked in Sign-In with Ethereum messages.
+    // Required when an EVM chain is enabled
+    siwe_domain: Option<String>,
+    siwe_uri: Option<String>,
     // Sui chain configuration
-    sui_rpc: Option<String>,
-    sui_contract: Option<String>,
-    sui_shares_trading_object_id: Option<String>,
+    sui_rpc: Vec<String>,
+    sui_ws_rpc: Option<String>,
+    sui_deployments: Vec<SuiDeploymentConfig>,
+    sui_sync_mode: SuiSyncMode,
+    sui_start_checkpoint: Option<u64>,
+    // OpenID providers trusted for zkLogin signatures
+    sui_zklogin_jwks: Vec<ZkLoginJwkSource>,
+    sui_zklogin_test_env: bool,
 }
 
-use crate::block_chain::monad::sync_trade_events;
+use crate::block_chain::registry::ChainRegistry;
+use crate::config::{EvmNetworkConfig, SuiDeploymentConfig, ZkLoginJwkSource, load_evm_networks, load_sui_deployments, parse_url_list, parse_zklogin_jwk_sources};
+use crate::block_chain::sui::SuiSyncMode;
 
 #[tokio::main]
 async fn main() {
@@ -61,24 +94,55 @@ async fn main() {
             .expect("TELEGRAM_BOT_TOKEN not set"),
         telegram_group_id: env::var("TELEGRAM_GROUP_ID")
             .expect("TELEGRAM_GROUP_ID not set"),
-        shares_contract: env::var("SHARES_CONTRACT_ADDRESS")
-            .expect("SHARES_CONTRACT_ADDRESS not set"),
-        chain_rpc: env::var("CHAIN_RPC")
-            .expect("CHAIN_RPC not set"),
         database_url: env::var("DATABASE_URL")
             .expect("DATABASE_URL not set"),
-        start_block: env::var("START_BLOCK")
-            .expect("START_BLOCK not set")
-            .parse()
-            .expect("START_BLOCK must be a number"),
-        sui_rpc: env::var("SUI_RPC").ok().map(|s| s),
-        sui_contract: env::var("SUI_CONTRACT").ok().map(|s| s),
-        sui_shares_trading_object_id: env::var("SUI_SHARES_TRADING_OBJECT_ID").ok().map(|s| s),
+        database_max_connections: env::var("DATABASE_MAX_CONNECTIONS")
+            .map(|s| s.parse().expect("DATABASE_MAX_CONNECTIONS must be a number"))
+            .unwrap_or(20),
+        evm_networks: load_evm_networks(),
+        enabled_chains: env::var("ENABLED_CHAINS").ok()
+            .map(|s| s.split(',').map(str::trim).filter(|chain| !chain.is_empty()).map(str::to_lowercase).collect()),
+        chain_min_block_range: env::var("CHAIN_MIN_BLOCK_RANGE")
+            .map(|s| s.parse().expect("CHAIN_MIN_BLOCK_RANGE must be a number"))
+            .unwrap_or(1),
+        chain_max_block_range: env::var("CHAIN_MAX_BLOCK_RANGE")
+            .map(|s| s.parse().expect("CHAIN_MAX_BLOCK_RANGE must be a number"))
+            .unwrap_or(5000),
+        rpc_requests_per_second: env::var("RPC_REQUESTS_PER_SECOND")
+            .map(|s| s.parse().expect("RPC_REQUESTS_PER_SECOND must be a number"))
+            .unwrap_or(25),
+        reconcile_interval_secs: env::var("RECONCILE_INTERVAL_SECS")
+            .map(|s| s.parse().expect("RECONCILE_INTERVAL_SECS must be a number"))
+            .unwrap_or(3600),
+        reconcile_sample_size: env::var("RECONCILE_SAMPLE_SIZE")
+            .map(|s| s.parse().expect("RECONCILE_SAMPLE_SIZE must be a number"))
+            .unwrap_or(100),
+        reconcile_auto_correct: env::var("RECONCILE_AUTO_CORRECT")
+            .map(|s| s.parse().expect("RECONCILE_AUTO_CORRECT must be true or false"))
+            .unwrap_or(false),
+        admin_token: env::var("ADMIN_TOKEN").ok().filter(|token| !token.is_empty()),
+        challenge_ttl_secs: env::var("CHALLENGE_TTL_SECS")
+            .map(|s| s.parse().expect("CHALLENGE_TTL_SECS must be a number"))
+            .unwrap_or(300),
+        siwe_domain: env::var("SIWE_DOMAIN").ok().filter(|domain| !domain.is_empty()),
+        siwe_uri: env::var("SIWE_URI").ok().filter(|uri| !uri.is_empty()),
+        sui_rpc: env::var("SUI_RPC").map(|s| parse_url_list(&s)).unwrap_or_default(),
+        sui_ws_rpc: env::var("SUI_WS_RPC").ok(),
+        sui_deployments: load_sui_deployments(),
+        sui_sync_mode: env::var("SUI_SYNC_MODE")
+            .map(|s| s.parse().expect("SUI_SYNC_MODE must be 'events' or 'checkpoints'"))
+            .unwrap_or(SuiSyncMode::Events),
+        sui_start_checkpoint: env::var("SUI_START_CHECKPOINT").ok()
+            .map(|s| s.parse().expect("SUI_START_CHECKPOINT must be a number")),
+        sui_zklogin_jwks: env::var("SUI_ZKLOGIN_JWKS").map(|s| parse_zklogin_jwk_sources(&s)).unwrap_or_default(),
+        sui_zklogin_test_env: env::var("SUI_ZKLOGIN_TEST_ENV")
+            .map(|s| s.parse().expect("SUI_ZKLOGIN_TEST_ENV must be true or false"))
+            .unwrap_or(false),
     };
     
     // Initialize database connection pool
     let pool = PgPoolOptions::new()
-        .max_connections(5)
+        .max_connections(config.database_max_connections)
         .connect(&config.database_url)
         .await
         .expect("Failed to connect to database");
@@ -86,6 +150,23 @@ async fn main() {
     // Initialize database tables
     //init_db(&pool).await.expect("Failed to initialize database");
     
+    // Chains are created once and shared by the indexers, workers and routes
+    let registry = Arc::new(ChainRegistry::from_config(Arc::new(config.clone()))
+        .expect("Invalid chain configuration"));
+    println!("Enabled chains: {:?}", registry.chain_types());
+    registry.assign_legacy_deployments(&pool).await
+        .expect("Failed to assign legacy rows to deployments");
+    
+    // `reindex` rebuilds holdings from the ledger and exits instead of starting the server
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("reindex") {
+        if let Err(e) = reindex::run(&args[1..], &registry, &pool).await {
+            eprintln!("Reindex failed: {:?}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    
     
     
     // Set up signal handler for graceful shutdown
@@ -105,27 +186,47 @@ async fn main() {
         }
     });
     
+    // Retry dead-lettered Trade events with backoff
+    tokio::spawn(run_failed_event_retries(pool.clone()));
+
+    // Execute Telegram permission changes queued by the indexer
+    tokio::spawn(run_outbox_worker(pool.clone()));
+    
+    // Re-scan ranges where the supply continuity check found a missed event
+    tokio::spawn(run_supply_rescans(registry.clone(), pool.clone()));
+    
+    // Periodically compare indexed holdings with on-chain balances
+    if config.reconcile_interval_secs > 0 {
+        let reconciler = Reconciler::new(Arc::new(config.clone()), registry.clone(), pool.clone());
+        tokio::spawn(reconciler.run(Duration::from_secs(config.reconcile_interval_secs)));
+    }
+    
     let config_clone = config.clone();
     let pool_clone = pool.clone();
+    let registry_clone = registry.clone();
     let http_server = HttpServer::new(move || {
         let cors = Cors::permissive();
         App::new()
             .wrap(cors)
             .app_data(web::Data::new(config_clone.clone()))
             .app_data(web::Data::new(pool_clone.clone()))
+            .app_data(web::Data::from(registry_clone.clone()))
+            .service(issue_challenge)
             .service(handle_verify)
             .service(handle_add_tg_bot)
             .service(get_agents)
             .service(get_agent_by_name)
             .service(get_agent_detail)
             .service(get_user_shares_handler)
+            .service(get_failed_events)
+            .service(replay_failed_event)
     })
         .bind("0.0.0.0:8088").unwrap()
         .run();
     
     // Create futures for all main tasks
     let server_future = http_server;
-    let sync_future = sync_trade_events(config, pool);
+    let sync_future = registry.sync_all(&pool);
     
     // Run all tasks concurrently and terminate when either completes or shutdown signal received
     tokio::select! {
@@ -0,0 +1,152 @@
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::Result;
+use sqlx::PgPool;
+use sqlx::types::BigDecimal;
+
+use crate::block_chain::registry::ChainRegistry;
+use crate::block_chain::outbox::enqueue_access_change;
+use crate::block_chain::trade::reevaluate_access;
+use crate::db::models::UserShares;
+use crate::db::operations::{correct_trade_balance, get_trades_page, get_user_subject_shares, record_share_discrepancy, sample_trades};
+use crate::AppConfig;
+
+/// Holdings fetched per page during a full scan
+const SCAN_PAGE_SIZE: i64 = 500;
+/// Delay before re-checking a mismatch, so the indexer can catch up with blocks
+/// that are on chain but not yet confirmed
+const RECHECK_DELAY: Duration = Duration::from_secs(30);
+
+/// Compares indexed holdings in `trades` with on-chain balances and records discrepancies
+pub struct Reconciler {
+    pool: PgPool,
+    config: Arc<AppConfig>,
+    chains: Arc<ChainRegistry>,
+}
+
+/// Outcome of one reconciliation pass
+#[derive(Debug, Default)]
+struct ReconcileStats {
+    checked: usize,
+    discrepancies: usize,
+    corrected: usize,
+    failed: usize,
+}
+
+impl Reconciler {
+    pub fn new(config: Arc<AppConfig>, chains: Arc<ChainRegistry>, pool: PgPool) -> Self {
+        Self {
+            pool,
+            config,
+            chains,
+        }
+    }
+
+    /// Reconcile every `interval`: a random sample of `RECONCILE_SAMPLE_SIZE` holdings, or all of them when 0
+    pub async fn run(mut self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match self.reconcile_once().await {
+                Ok(stats) => println!(
+                    "Reconciled {} holdings: {} discrepancies, {} corrected, {} failed to check",
+                    stats.checked, stats.discrepancies, stats.corrected, stats.failed
+                ),
+                Err(e) => println!("Reconciliation failed: {:?}", e),
+            }
+        }
+    }
+
+    async fn reconcile_once(&mut self) -> Result<ReconcileStats> {
+        let mut stats = ReconcileStats::default();
+
+        if self.config.reconcile_sample_size > 0 {
+            let holdings = sample_trades(&self.pool, self.config.reconcile_sample_size).await?;
+            self.reconcile_holdings(&holdings, &mut stats).await;
+            return Ok(stats);
+        }
+
+        let mut after: Option<UserShares> = None;
+        loop {
+            let holdings = get_trades_page(&self.pool, after.as_ref(), SCAN_PAGE_SIZE).await?;
+            self.reconcile_holdings(&holdings, &mut stats).await;
+            match holdings.into_iter().last() {
+                Some(last) => after = Some(last),
+                None => return Ok(stats),
+            }
+        }
+    }
+
+    async fn reconcile_holdings(&mut self, holdings: &[UserShares], stats: &mut ReconcileStats) {
+        let mut mismatched = Vec::new();
+        for holding in holdings {
+            stats.checked += 1;
+            match self.onchain_balance(holding).await {
+                Ok(onchain_amount) if onchain_amount == holding.share_amount => {},
+                Ok(_) => mismatched.push(holding),
+                Err(e) => {
+                    stats.failed += 1;
+                    println!("Failed to reconcile {} holding {} of {}: {:?}", holding.chain_type, holding.trader, holding.subject, e);
+                }
+            }
+        }
+        if mismatched.is_empty() {
+            return;
+        }
+
+        // Re-check all mismatches after a single delay, the indexer may just be behind the chain head
+        tokio::time::sleep(RECHECK_DELAY).await;
+        for holding in mismatched {
+            match self.recheck_holding(holding).await {
+                Ok(None) => {},
+                Ok(Some(corrected)) => {
+                    stats.discrepancies += 1;
+                    if corrected {
+                        stats.corrected += 1;
+                    }
+                },
+                Err(e) => {
+                    stats.failed += 1;
+                    println!("Failed to reconcile {} holding {} of {}: {:?}", holding.chain_type, holding.trader, holding.subject, e);
+                }
+            }
+        }
+    }
+
+    /// Re-read both sides of a holding that differed from the chain, returns whether it was
+    /// corrected when it still differs
+    async fn recheck_holding(&mut self, holding: &UserShares) -> Result<Option<bool>> {
+        let holding = UserShares {
+            share_amount: get_user_subject_shares(&self.pool, &holding.trader, &holding.subject, &holding.chain_type, &holding.deployment).await?,
+            ..holding.clone()
+        };
+        let onchain_amount = self.onchain_balance(&holding).await?;
+        if onchain_amount == holding.share_amount {
+            return Ok(None);
+        }
+
+        println!(
+            "Share discrepancy on {} {}: trader {} subject {} indexed {} on chain {}",
+            holding.chain_type, holding.deployment, holding.trader, holding.subject, holding.share_amount, onchain_amount
+        );
+
+        let mut tx = self.pool.begin().await?;
+        let corrected = self.config.reconcile_auto_correct
+            && correct_trade_balance(&mut tx, &holding, &onchain_amount).await?;
+        let discrepancy_id = record_share_discrepancy(&mut tx, &holding, &onchain_amount, corrected).await?;
+        if corrected {
+            if let Some(change) = reevaluate_access(&mut tx, &holding.chain_type, &holding.deployment, &holding.trader, &holding.subject, &onchain_amount).await? {
+                enqueue_access_change(&mut tx, &change, &holding.chain_type, &format!("discrepancy:{}", discrepancy_id)).await?;
+            }
+        }
+        tx.commit().await?;
+
+        Ok(Some(corrected))
+    }
+
+    async fn onchain_balance(&mut self, holding: &UserShares) -> Result<BigDecimal> {
+        let blockchain = self.chains.require(&holding.chain_type)?;
+        let balance = blockchain.get_shares_balance(&holding.deployment, &holding.subject, &holding.trader).await?;
+        Ok(BigDecimal::from(balance))
+    }
+}
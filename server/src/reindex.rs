@@ -0,0 +1,139 @@
+use anyhow::{Result, anyhow};
+use sqlx::PgPool;
+
+use crate::block_chain::registry::ChainRegistry;
+use crate::db::operations::{count_negative_shadow_holdings, count_shadow_differences, lock_trade_projection, rebuild_trades_shadow, swap_in_trades_shadow};
+
+const USAGE: &str = "Usage: reindex <chain> [--from <block|checkpoint> --to <block|checkpoint>] [--dry-run] [--force]";
+
+/// Arguments of the reindex command
+#[derive(Debug, PartialEq)]
+struct ReindexOptions {
+    chain_type: String,
+    /// Inclusive block (EVM) or checkpoint (Sui) range re-fetched from the chain first
+    range: Option<(u64, u64)>,
+    /// Rebuild and verify the shadow rows without swapping them in
+    dry_run: bool,
+    /// Swap the shadow rows in even when they differ from the current holdings
+    force: bool,
+}
+
+impl ReindexOptions {
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut args = args.iter();
+        let chain_type = match args.next().map(String::as_str) {
+            Some(chain_type) if !chain_type.starts_with("--") => chain_type.to_string(),
+            _ => return Err(anyhow!(USAGE)),
+        };
+
+        let mut from = None;
+        let mut to = None;
+        let mut dry_run = false;
+        let mut force = false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--from" => from = Some(parse_number(args.next())?),
+                "--to" => to = Some(parse_number(args.next())?),
+                "--dry-run" => dry_run = true,
+                "--force" => force = true,
+                _ => return Err(anyhow!("Unknown argument {}\n{}", arg, USAGE)),
+            }
+        }
+
+        let range = match (from, to) {
+            (Some(from), Some(to)) if from <= to => Some((from, to)),
+            (None, None) => None,
+            _ => return Err(anyhow!("--from and --to must both be set, with --from <= --to\n{}", USAGE)),
+        };
+
+        Ok(Self { chain_type, range, dry_run, force })
+    }
+}
+
+fn parse_number(arg: Option<&String>) -> Result<u64> {
+    let arg = arg.ok_or_else(|| anyhow!(USAGE))?;
+    arg.parse().map_err(|e| anyhow!("Invalid number {}: {:?}\n{}", arg, e, USAGE))
+}
+
+/// Run the reindex command: optionally re-fetch a range from the chain into the ledger,
+/// then rebuild the chain's holdings from the ledger and swap them into `trades`
+pub async fn run(args: &[String], chains: &ChainRegistry, pool: &PgPool) -> Result<()> {
+    let options = ReindexOptions::parse(args)?;
+    let blockchain = chains.require(&options.chain_type)?;
+
+    if let Some((from, to)) = options.range {
+        let found = blockchain.backfill_range(pool, from, to).await?;
+        println!("Re-fetched {} {} events in range {} to {}", found, options.chain_type, from, to);
+    }
+
+    rebuild_holdings(pool, &options.chain_type, options.dry_run, options.force).await
+}
+
+/// Replay the ledger into `trades_shadow`, verify it and atomically replace the chain's
+/// rows in `trades`. The indexer is blocked from writing while the transaction runs.
+/// Rebuilt holdings that differ from `trades` are only swapped in with `force`.
+async fn rebuild_holdings(pool: &PgPool, chain_type: &str, dry_run: bool, force: bool) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    lock_trade_projection(&mut tx).await?;
+
+    let rebuilt = rebuild_trades_shadow(&mut tx, chain_type).await?;
+    let negative = count_negative_shadow_holdings(&mut tx, chain_type).await?;
+    let differences = count_shadow_differences(&mut tx, chain_type).await?;
+    println!("Rebuilt {} {} holdings from the ledger, {} differ from trades", rebuilt, chain_type, differences);
+
+    // Keep the shadow rows for inspection when they are not swapped in
+    if negative > 0 {
+        tx.commit().await?;
+        return Err(anyhow!(
+            "{} rebuilt {} holdings are negative, the ledger is missing events. trades is unchanged, see trades_shadow",
+            negative, chain_type
+        ));
+    }
+    if dry_run {
+        tx.commit().await?;
+        println!("Dry run, trades is unchanged, see trades_shadow");
+        return Ok(());
+    }
+    // Holdings partly indexed before the ledger existed would lose balance the ledger does not explain
+    if differences > 0 && !force {
+        tx.commit().await?;
+        return Err(anyhow!(
+            "{} rebuilt {} holdings differ from trades. trades is unchanged, see trades_shadow and rerun with --force to swap them in",
+            differences, chain_type
+        ));
+    }
+
+    swap_in_trades_shadow(&mut tx, chain_type).await?;
+    tx.commit().await?;
+    println!("Swapped rebuilt {} holdings into trades", chain_type);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_reindex_options() {
+        assert_eq!(
+            ReindexOptions::parse(&args(&["sui", "--from", "10", "--to", "20", "--dry-run"])).unwrap(),
+            ReindexOptions { chain_type: "sui".to_string(), range: Some((10, 20)), dry_run: true, force: false }
+        );
+        assert_eq!(
+            ReindexOptions::parse(&args(&["monad"])).unwrap(),
+            ReindexOptions { chain_type: "monad".to_string(), range: None, dry_run: false, force: false }
+        );
+        assert_eq!(
+            ReindexOptions::parse(&args(&["monad", "--force"])).unwrap(),
+            ReindexOptions { chain_type: "monad".to_string(), range: None, dry_run: false, force: true }
+        );
+        assert!(ReindexOptions::parse(&args(&[])).is_err());
+        assert!(ReindexOptions::parse(&args(&["--dry-run"])).is_err());
+        assert!(ReindexOptions::parse(&args(&["monad", "--from", "10"])).is_err());
+        assert!(ReindexOptions::parse(&args(&["monad", "--from", "20", "--to", "10"])).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use actix_web::{get, HttpRequest, HttpResponse, post, Responder, web};
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::block_chain::dead_letter::retry_failed_event;
+use crate::db::models::FailedEvent;
+use crate::db::operations::{list_failed_events, reset_failed_event};
+use crate::AppConfig;
+
+/// Header carrying `ADMIN_TOKEN`, admin endpoints are disabled when it is not configured
+const ADMIN_TOKEN_HEADER: &str = "X-Admin-Token";
+
+#[derive(Debug, Serialize)]
+pub struct FailedEventResponse {
+    pub id: i64,
+    pub chain_type: String,
+    pub deployment: String,
+    pub tx_hash: String,
+    pub log_index: i64,
+    pub block_number: Option<i64>,
+    pub checkpoint: Option<i64>,
+    pub trader: String,
+    pub subject: String,
+    pub is_buy: bool,
+    pub share_amount: String,
+    pub error: String,
+    pub attempts: i32,
+    pub status: String,
+    pub next_retry_at: String,
+    pub raw_event: serde_json::Value,
+}
+
+impl From<FailedEvent> for FailedEventResponse {
+    fn from(event: FailedEvent) -> Self {
+        Self {
+            id: event.id,
+            chain_type: event.chain_type,
+            deployment: event.deployment,
+            tx_hash: event.tx_hash,
+            log_index: event.log_index,
+            block_number: event.block_number,
+            checkpoint: event.checkpoint,
+            trader: event.trader,
+            subject: event.subject,
+            is_buy: event.is_buy,
+            share_amount: event.share_amount.to_string(),
+            error: event.error,
+            attempts: event.attempts,
+            status: event.status,
+            next_retry_at: event.next_retry_at.to_string(),
+            raw_event: event.raw_event,
+        }
+    }
+}
+
+/// Reject the request unless it carries the configured admin token
+fn authorize(req: &HttpRequest, config: &AppConfig) -> Result<(), HttpResponse> {
+    let Some(admin_token) = &config.admin_token else {
+        return Err(HttpResponse::Forbidden().json(serde_json::json!({
+            "success": false,
+            "error": "Admin API is disabled"
+        })));
+    };
+
+    let token = req.headers().get(ADMIN_TOKEN_HEADER).and_then(|value| value.to_str().ok());
+    if token != Some(admin_token.as_str()) {
+        return Err(HttpResponse::Unauthorized().json(serde_json::json!({
+            "success": false,
+            "error": "Invalid admin token"
+        })));
+    }
+    Ok(())
+}
+
+#[get("/admin/failed_events")]
+pub async fn get_failed_events(
+    req: HttpRequest,
+    query: web::Query<HashMap<String, String>>,
+    config: web::Data<AppConfig>,
+    pool: web::Data<PgPool>,
+) -> impl Responder {
+    if let Err(response) = authorize(&req, &config) {
+        return response;
+    }
+
+    // Parse pagination parameters
+    let page = query.get("page").and_then(|p| p.parse::<i64>().ok()).unwrap_or(1);
+    let page_size = query.get("page_size").and_then(|ps| ps.parse::<i64>().ok()).unwrap_or(20);
+
+    if page < 1 || page_size < 1 {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "error": "Invalid pagination parameters"
+        }));
+    }
+
+    let result = list_failed_events(
+        pool.get_ref(),
+        query.get("status").map(String::as_str),
+        query.get("chain_type").map(String::as_str),
+        page_size,
+        (page - 1) * page_size,
+    ).await;
+
+    match result {
+        Ok(events) => {
+            let events: Vec<FailedEventResponse> = events.into_iter().map(FailedEventResponse::from).collect();
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "events": events,
+                "page": page,
+                "page_size": page_size
+            }))
+        },
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "error": format!("Database error: {}", e)
+        })),
+    }
+}
+
+/// Retry a dead-lettered event right away, with a fresh attempt budget for automatic retries
+#[post("/admin/failed_events/{id}/replay")]
+pub async fn replay_failed_event(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    config: web::Data<AppConfig>,
+    pool: web::Data<PgPool>,
+) -> impl Responder {
+    if let Err(response) = authorize(&req, &config) {
+        return response;
+    }
+    let id = path.into_inner();
+
+    let event = match reset_failed_event(pool.get_ref(), id).await {
+        Ok(Some(event)) => event,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "success": false,
+                "error": "Failed event not found or already resolved"
+            }));
+        },
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "error": format!("Database error: {}", e)
+            }));
+        }
+    };
+
+    match retry_failed_event(pool.get_ref(), &event).await {
+        Ok(applied) => {
+            println!("Replayed dead-lettered event {}, applied: {}", id, applied);
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "applied": applied
+            }))
+        },
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "error": format!("Replay failed: {:#}", e)
+        })),
+    }
+}
@@ -3,6 +3,7 @@ use actix_web::{get, HttpResponse, post, Responder, web};
 use serde::{Deserialize, Serialize, Serializer};
 use sqlx::PgPool;
 use time::PrimitiveDateTime;
+use crate::block_chain::registry::ChainRegistry;
 
 // Custom datetime serialization function
 fn serialize_datetime<S>(
@@ -59,6 +60,8 @@ pub struct AddTelegramBotRequest {
     pub agent_name: String,
     pub invite_url: String,
     pub bio: Option<String>,
+    pub chain_type: Option<String>, // Default is monad
+    pub deployment: Option<String>, // Default is the chain's first deployment
 }
 
 #[derive(Debug, Serialize)]
@@ -72,17 +75,40 @@ pub struct AddTelegramBotResponse {
 async fn handle_add_tg_bot(
     data: web::Json<AddTelegramBotRequest>,
     pool: web::Data<PgPool>,
+    chains: web::Data<ChainRegistry>,
 ) -> impl Responder {
+    let chain_type = data.chain_type.clone().unwrap_or_else(|| "monad".to_string());
+    let Some(blockchain) = chains.get(&chain_type) else {
+        return HttpResponse::BadRequest().json(AddTelegramBotResponse {
+            success: false,
+            error: Some(format!("Unsupported chain type: {}", chain_type)),
+        });
+    };
+    // The bot gates access on the shares of exactly one deployment
+    let deployments = blockchain.deployments();
+    let deployment = match &data.deployment {
+        Some(deployment) => deployment.to_lowercase(),
+        None => deployments.first().cloned().unwrap_or_default(),
+    };
+    if !deployments.contains(&deployment) {
+        return HttpResponse::BadRequest().json(AddTelegramBotResponse {
+            success: false,
+            error: Some(format!("Unknown deployment {} on {}", deployment, chain_type)),
+        });
+    }
+
     let subject_address = data.subject_address.to_lowercase().trim_start_matches("0x").to_owned();
     // Store bot information in database
     let result = sqlx::query!(
-        "INSERT INTO telegram_bots (agent_name, bot_token, chat_group_id, subject_address, invite_url, bio) VALUES ($1, $2, $3, $4, $5, $6)",
+        "INSERT INTO telegram_bots (agent_name, bot_token, chat_group_id, subject_address, invite_url, bio, chain_type, deployment) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
         data.agent_name,
         data.bot_token,
         data.chat_group_id,
         subject_address.clone(),
         data.invite_url,
-        data.bio
+        data.bio,
+        chain_type,
+        deployment
     )
         .execute(pool.get_ref())
         .await;
@@ -0,0 +1,243 @@
+use std::str::FromStr;
+use actix_web::{HttpResponse, post, Responder, web};
+use ethers::types::Address;
+use ethers::utils::hex;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use time::{Duration, OffsetDateTime};
+use time::format_description::well_known::Rfc3339;
+
+use crate::block_chain::registry::ChainRegistry;
+use crate::block_chain::siwe::SiweMessage;
+use crate::db::models::AuthChallenge;
+use crate::db::operations::{create_auth_challenge, delete_stale_auth_challenges};
+use crate::AppConfig;
+
+#[derive(Debug, Deserialize)]
+pub struct IssueChallengeRequest {
+    pub telegram_id: String,
+    pub chat_id: String,
+    pub chain_type: Option<String>, // Default is monad
+    /// Address that will sign, required on EVM chains where it is part of the SIWE message
+    pub address: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IssueChallengeResponse {
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    /// Text to sign with the wallet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl IssueChallengeResponse {
+    fn error(error: String) -> Self {
+        Self {
+            success: false,
+            nonce: None,
+            message: None,
+            expires_at: None,
+            error: Some(error),
+        }
+    }
+}
+
+/// Sign-In with Ethereum message binding the nonce to the Telegram user, group, network and expiry,
+/// `None` when the site is not configured
+fn siwe_challenge_message(
+    config: &AppConfig,
+    chain_id: u64,
+    address: Address,
+    nonce: &str,
+    request: &IssueChallengeRequest,
+    issued_at: OffsetDateTime,
+    expires_at: OffsetDateTime,
+) -> Option<String> {
+    Some(SiweMessage {
+        domain: config.siwe_domain.clone()?,
+        address,
+        statement: Some(format!("Join the Telegram group {} as Telegram user {}.", request.chat_id, request.telegram_id)),
+        uri: config.siwe_uri.clone()?,
+        chain_id,
+        nonce: nonce.to_string(),
+        issued_at,
+        expiration_time: Some(expires_at),
+        not_before: None,
+        request_id: None,
+        resources: Vec::new(),
+    }.to_string())
+}
+
+/// Human-readable text binding the nonce to the Telegram user, group, chain and expiry
+pub fn challenge_message(
+    nonce: &str,
+    telegram_id: &str,
+    chat_id: &str,
+    chain_type: &str,
+    issued_at: OffsetDateTime,
+    expires_at: OffsetDateTime,
+) -> Result<String, time::error::Format> {
+    Ok(format!(
+        "Sign this message to prove you own this address and join the Telegram group.\n\
+        \n\
+        Telegram ID: {}\n\
+        Chat ID: {}\n\
+        Chain: {}\n\
+        Nonce: {}\n\
+        Issued At: {}\n\
+        Expiration Time: {}",
+        telegram_id,
+        chat_id,
+        chain_type,
+        nonce,
+        issued_at.format(&Rfc3339)?,
+        expires_at.format(&Rfc3339)?,
+    ))
+}
+
+// Issue a single-use nonce for a Telegram user to sign before /verify-signature
+#[post("/challenge")]
+async fn issue_challenge(
+    data: web::Json<IssueChallengeRequest>,
+    config: web::Data<AppConfig>,
+    chains: web::Data<ChainRegistry>,
+    pool: web::Data<PgPool>,
+) -> impl Responder {
+    let chain_type = data.chain_type.clone().unwrap_or_else(|| "monad".to_string());
+    if chains.get(&chain_type).is_none() {
+        return HttpResponse::BadRequest().json(IssueChallengeResponse::error(
+            format!("Unsupported chain type: {}", chain_type),
+        ));
+    }
+    if data.telegram_id.parse::<u64>().is_err() {
+        return HttpResponse::BadRequest().json(IssueChallengeResponse::error(
+            format!("Invalid Telegram ID: {}", data.telegram_id),
+        ));
+    }
+
+    let bot = sqlx::query!(
+        "SELECT agent_name FROM telegram_bots WHERE chat_group_id = $1 AND chain_type = $2 LIMIT 1",
+        data.chat_id,
+        chain_type
+    )
+    .fetch_optional(pool.get_ref())
+    .await;
+    match bot {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            return HttpResponse::BadRequest().json(IssueChallengeResponse::error(
+                format!("Bot not found for this chat_id in {} chain", chain_type),
+            ));
+        },
+        Err(e) => {
+            println!("Failed to query bot info: {:?}", e);
+            return HttpResponse::InternalServerError().json(IssueChallengeResponse::error(
+                format!("Database query failed: {}", e),
+            ));
+        }
+    }
+
+    let nonce = hex::encode(ethers::core::rand::random::<[u8; 16]>());
+    let now = OffsetDateTime::now_utc();
+    // Whole seconds, as shown in the signed message
+    let issued_at = now.replace_nanosecond(0).unwrap_or(now);
+    let expires_at = issued_at + Duration::seconds(config.challenge_ttl_secs as i64);
+    // EVM wallets sign in with Ethereum, other chains sign the plain challenge text
+    let evm_network = config.evm_networks.iter().find(|network| network.name == chain_type);
+    let message = match evm_network {
+        Some(network) => {
+            let address = match data.address.as_deref().map(Address::from_str) {
+                Some(Ok(address)) => address,
+                Some(Err(e)) => {
+                    return HttpResponse::BadRequest().json(IssueChallengeResponse::error(
+                        format!("Invalid address: {}", e),
+                    ));
+                },
+                None => {
+                    return HttpResponse::BadRequest().json(IssueChallengeResponse::error(
+                        format!("address is required on {}", chain_type),
+                    ));
+                }
+            };
+            match siwe_challenge_message(&config, network.chain_id, address, &nonce, &data, issued_at, expires_at) {
+                Some(message) => message,
+                None => {
+                    return HttpResponse::InternalServerError().json(IssueChallengeResponse::error(
+                        "Sign-In with Ethereum is not configured".to_string(),
+                    ));
+                }
+            }
+        },
+        None => match challenge_message(&nonce, &data.telegram_id, &data.chat_id, &chain_type, issued_at, expires_at) {
+            Ok(message) => message,
+            Err(e) => {
+                return HttpResponse::InternalServerError().json(IssueChallengeResponse::error(
+                    format!("Cannot format challenge: {}", e),
+                ));
+            }
+        },
+    };
+
+    let challenge = AuthChallenge {
+        nonce: nonce.clone(),
+        telegram_id: data.telegram_id.clone(),
+        chat_id: data.chat_id.clone(),
+        chain_type,
+        message: message.clone(),
+        expires_at,
+        used_at: None,
+    };
+    if let Err(e) = create_auth_challenge(pool.get_ref(), &challenge).await {
+        println!("Failed to store challenge: {:?}", e);
+        return HttpResponse::InternalServerError().json(IssueChallengeResponse::error(
+            format!("Failed to store challenge: {}", e),
+        ));
+    }
+    if let Err(e) = delete_stale_auth_challenges(pool.get_ref()).await {
+        println!("Failed to delete stale challenges: {:?}", e);
+    }
+
+    HttpResponse::Ok().json(IssueChallengeResponse {
+        success: true,
+        nonce: Some(nonce),
+        message: Some(message),
+        expires_at: expires_at.format(&Rfc3339).ok(),
+        error: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_challenge_message() {
+        let issued_at = OffsetDateTime::from_unix_timestamp(1_760_000_000).unwrap();
+        let message = challenge_message(
+            "3f1c9a",
+            "7346290155",
+            "-1001234567890",
+            "sui",
+            issued_at,
+            issued_at + Duration::minutes(5),
+        ).unwrap();
+
+        assert_eq!(
+            message,
+            "Sign this message to prove you own this address and join the Telegram group.\n\
+            \n\
+            Telegram ID: 7346290155\n\
+            Chat ID: -1001234567890\n\
+            Chain: sui\n\
+            Nonce: 3f1c9a\n\
+            Issued At: 2025-10-09T08:53:20Z\n\
+            Expiration Time: 2025-10-09T08:58:20Z"
+        );
+    }
+}
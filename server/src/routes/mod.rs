@@ -1,3 +1,5 @@
+pub mod admin;
+pub mod challenge;
 pub mod user;
 pub mod agent;
 pub mod signature;
\ No newline at end of file
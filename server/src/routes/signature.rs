@@ -1,23 +1,19 @@
-use std::sync::Arc;
 use actix_web::{HttpResponse, post, Responder, web};
-use ethers::addressbook::Address;
-use ethers::prelude::Signature;
-use ethers::utils::{hash_message, hex};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
-use crate::AppConfig;
 use teloxide::Bot;
 use teloxide::prelude::{Requester, UserId};
 use teloxide::types::ChatPermissions;
-use crate::block_chain::{Blockchain, create_blockchain};
+use time::OffsetDateTime;
+use crate::block_chain::registry::ChainRegistry;
+use crate::db::operations::{consume_auth_challenge, get_auth_challenge};
 
 #[derive(Debug, Deserialize)]
 pub struct ChallengeRequest {
-    pub challenge: String,
-    pub chat_id: String,
+    /// Nonce issued by POST /challenge, whose message was signed
+    pub nonce: String,
     pub signature: String,
     pub user: String,
-    pub chain_type: Option<String>, // Add chain type, default is monad
 }
 
 #[derive(Debug, Serialize)]
@@ -26,47 +22,64 @@ pub struct ChallengeResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
 }
-pub fn verify_signature(
-    challenge: &str,
-    signature: &str,
-) -> Result<Address, String> {
-    let sig_bytes = hex::decode(signature)
-        .map_err(|e| format!("Invalid signature hex: {}", e))?;
-
-    if sig_bytes.len() != 65 {
-        return Err("Signature must be 65 bytes".into());
-    }
-
-    let message_hash = hash_message(challenge);
-    let signature = Signature::try_from(sig_bytes.as_slice()).map_err(|e| format!("Invalid signature: {}!",e))?;
-    let recovered_address = signature
-        .recover(message_hash)
-        .map_err(|e| format!("Recovery failed: {}", e))?;
-    Ok(recovered_address)
-}
 
 
 #[post("/verify-signature")]
 async fn handle_verify(
     data: web::Json<ChallengeRequest>,
-    config: web::Data<AppConfig>,
+    chains: web::Data<ChainRegistry>,
     pool: web::Data<PgPool>,
 ) -> impl Responder {
     println!("Received request: {:?}", data);
-    // Determine chain type, default is monad
-    let chain_type = data.chain_type.clone().unwrap_or_else(|| "monad".to_string());
+    // The challenge decides the Telegram user, group and chain the signature is for
+    let challenge = match get_auth_challenge(pool.get_ref(), &data.nonce).await {
+        Ok(Some(challenge)) => challenge,
+        Ok(None) => {
+            return HttpResponse::BadRequest().json(ChallengeResponse {
+                success: false,
+                error: Some("Unknown challenge".to_string()),
+            });
+        },
+        Err(e) => {
+            println!("Failed to query challenge: {:?}", e);
+            return HttpResponse::InternalServerError().json(ChallengeResponse {
+                success: false,
+                error: Some(format!("Database query failed: {}", e)),
+            });
+        }
+    };
+    if challenge.used_at.is_some() {
+        return HttpResponse::BadRequest().json(ChallengeResponse {
+            success: false,
+            error: Some("Challenge already used".to_string()),
+        });
+    }
+    if challenge.expires_at <= OffsetDateTime::now_utc() {
+        return HttpResponse::BadRequest().json(ChallengeResponse {
+            success: false,
+            error: Some("Challenge expired".to_string()),
+        });
+    }
+
+    let chain_type = challenge.chain_type.clone();
+    let Some(blockchain) = chains.get(&chain_type) else {
+        return HttpResponse::BadRequest().json(ChallengeResponse {
+            success: false,
+            error: Some(format!("Unsupported chain type: {}", chain_type)),
+        });
+    };
 
     // Query bot info including subject_address from telegram_bots table using chat_id
     let bot_info = match sqlx::query!(
-        "SELECT bot_token, chat_group_id, subject_address FROM telegram_bots WHERE chat_group_id = $1 AND chain_type = $2",
-        data.chat_id,
+        "SELECT bot_token, chat_group_id, subject_address, deployment FROM telegram_bots WHERE chat_group_id = $1 AND chain_type = $2",
+        challenge.chat_id,
         chain_type
     )
     .fetch_optional(pool.get_ref())
     .await {
         Ok(Some(info)) => info,
         Ok(None) => {
-            println!("No bot info found for chat_id: {} and chain: {}", data.chat_id, chain_type);
+            println!("No bot info found for chat_id: {} and chain: {}", challenge.chat_id, chain_type);
             return HttpResponse::BadRequest().json(ChallengeResponse {
                 success: false,
                 error: Some(format!("Bot not found for this chat_id in {} chain", chain_type)),
@@ -81,59 +94,74 @@ async fn handle_verify(
         }
     };
 
-    // Create blockchain instance for the appropriate chain
-    let blockchain = create_blockchain(&chain_type, Arc::new(config.get_ref().clone()));
-    
-    let own_shares = match blockchain.verify_signature(
-        if chain_type == "sui" { &data.user } else { &data.challenge },
-        &data.signature,
-    ) {
-        Ok(verified_address) => {
-            println!("Verified address is {}", verified_address);
-            
-            if data.user == verified_address {
-                println!("Address matches! Verified: {}, Expected: {}", verified_address, data.user);
-                // When address matches, save user address and Telegram ID to database
-                let telegram_id = &data.challenge;
+    let verified_address = match blockchain.verify_signature(&challenge.message, &data.signature).await {
+        Ok(verified_address) => verified_address,
+        Err(e) => {
+            println!("Verify signature failed: {:?}", e);
+            return HttpResponse::Unauthorized().json(ChallengeResponse {
+                success: false,
+                error: Some(format!("Invalid signature: {}", e)),
+            });
+        }
+    };
+    println!("Verified address is {}", verified_address);
 
-                // Check if user address already exists
-                let result = sqlx::query!(
-                    "INSERT INTO user_mappings (address, telegram_id, chain_type)
-                     VALUES ($1, $2, $3)
-                     ON CONFLICT (address, chain_type) DO UPDATE SET telegram_id = $2",
-                    verified_address,
-                    telegram_id,
-                    chain_type
-                )
-                    .execute(pool.get_ref())
-                    .await;
+    // Verified addresses are lower-case hex without 0x prefix
+    if data.user.trim_start_matches("0x").to_lowercase() != verified_address {
+        println!("Address mismatch with signature! Verified: {}, Expected: {}", verified_address, data.user);
+        return HttpResponse::Unauthorized().json(ChallengeResponse {
+            success: false,
+            error: Some("Signature was not made by this user address".to_string()),
+        });
+    }
+    println!("Address matches! Verified: {}, Expected: {}", verified_address, data.user);
 
-                if let Err(e) = result {
-                    println!("Failed to save user mapping: {:?}", e);
-                }
+    // Consume the nonce before acting on it, a concurrent replay finds it used
+    match consume_auth_challenge(pool.get_ref(), &challenge.nonce).await {
+        Ok(true) => {},
+        Ok(false) => {
+            return HttpResponse::BadRequest().json(ChallengeResponse {
+                success: false,
+                error: Some("Challenge already used or expired".to_string()),
+            });
+        },
+        Err(e) => {
+            println!("Failed to consume challenge: {:?}", e);
+            return HttpResponse::InternalServerError().json(ChallengeResponse {
+                success: false,
+                error: Some(format!("Database query failed: {}", e)),
+            });
+        }
+    }
+    // When address matches, save user address and Telegram ID to database
+    let telegram_id = &challenge.telegram_id;
 
-                // Get user's share balance
-                let has_shares = match blockchain.get_shares_balance(&bot_info.subject_address, &verified_address).await {
-                    Ok(balance) => {
-                        println!("User {} balance for subject {}: {}", verified_address, bot_info.subject_address, balance);
-                        balance > 0
-                    },
-                    Err(e) => {
-                        println!("Failed to get shares balance: {:?}", e);
-                        false
-                    }
-                };
+    // Check if user address already exists
+    let result = sqlx::query!(
+        "INSERT INTO user_mappings (address, telegram_id, chain_type)
+         VALUES ($1, $2, $3)
+         ON CONFLICT (address, chain_type) DO UPDATE SET telegram_id = $2",
+        verified_address,
+        telegram_id,
+        chain_type
+    )
+        .execute(pool.get_ref())
+        .await;
 
-                has_shares
-            } else {
-                println!("Address mismatch with signature! Verified: {}, Expected: {}", verified_address, data.user);
-                false
-            }
-        }
+    if let Err(e) = result {
+        println!("Failed to save user mapping: {:?}", e);
+    }
+
+    // Get user's share balance
+    let own_shares = match blockchain.get_shares_balance(&bot_info.deployment, &bot_info.subject_address, &verified_address).await {
+        Ok(balance) => {
+            println!("User {} balance for subject {}: {}", verified_address, bot_info.subject_address, balance);
+            balance > 0
+        },
         Err(e) => {
-            println!("Verify signature failed: {:?}",e);
+            println!("Failed to get shares balance: {:?}", e);
             false
-        },
+        }
     };
     
     if own_shares {
@@ -145,7 +173,8 @@ async fn handle_verify(
             | ChatPermissions::ADD_WEB_PAGE_PREVIEWS;
 
         let bot = Bot::new(bot_info.bot_token);
-        let user_id: u64 = data.challenge.parse().unwrap();
+        // Checked to be numeric when the challenge was issued
+        let user_id: u64 = challenge.telegram_id.parse().unwrap();
         match bot.restrict_chat_member(bot_info.chat_group_id, UserId(user_id), permissions).await {
             Ok(_) => {
                 return HttpResponse::Ok().json(ChallengeResponse {
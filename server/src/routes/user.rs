@@ -1,3 +1,4 @@
+use crate::block_chain::registry::ChainRegistry;
 use crate::db::operations::get_user_shares;
 use actix_web::{web, get};
 use serde::{Deserialize, Serialize};
@@ -14,6 +15,7 @@ pub struct UserSharesResponse {
 pub struct SubjectShare {
     subject_address: String,
     shares_amount: String,
+    deployment: String,
 }
 
 #[derive(Deserialize)]
@@ -26,11 +28,15 @@ pub struct PathParams {
 #[get("/users/{user_address}/shares/{chain_type}")]
 pub async fn get_user_shares_handler(
     pool: web::Data<PgPool>,
+    chains: web::Data<ChainRegistry>,
     path: web::Path<PathParams>,
 ) -> Result<web::Json<UserSharesResponse>, actix_web::Error> {
     let path_params = path.into_inner();
     let user_address = path_params.user_address.to_lowercase().trim_start_matches("0x").to_owned();
     let chain_type = path_params.chain_type;
+    if chains.get(&chain_type).is_none() {
+        return Err(actix_web::error::ErrorBadRequest(format!("Unsupported chain type: {}", chain_type)));
+    }
     
     println!("user_address: {:?}", user_address);
     println!("chain_type: {:?}", chain_type);
@@ -43,6 +49,7 @@ pub async fn get_user_shares_handler(
         .map(|share| SubjectShare {
             subject_address: share.subject,
             shares_amount: share.share_amount.to_string(),
+            deployment: share.deployment,
         })
         .collect();
     